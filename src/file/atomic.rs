@@ -0,0 +1,295 @@
+//! Atomic whole-file replacement
+
+use std::{ffi, fs, io, path, process};
+use std::sync::atomic;
+
+use super::{ensure_parent_dir, is_file, FileError, IncrementalNamer};
+
+/// Replaces the contents of the file at the given path atomically, recursively creating
+/// parent directories.
+///
+/// Readers and crashes observe either the previous contents or the new contents in
+/// full, never a partially written file (&#x261e; see
+/// [`AtomicFile`](struct.AtomicFile.html)).
+///
+/// # Examples
+///
+/// ```
+/// # use rs_utils::file::write_atomic;
+/// let temp_dir  = tempfile::Builder::new().prefix ("tmp").tempdir().unwrap();
+/// let file_path = temp_dir.path().join ("state/snapshot.json");
+/// write_atomic (&file_path, b"{\"version\":1}").unwrap();
+/// write_atomic (&file_path, b"{\"version\":2}").unwrap();
+/// assert_eq!(std::fs::read (&file_path).unwrap(), b"{\"version\":2}");
+/// ```
+///
+/// # Errors
+///
+/// &#x261e; See [`AtomicFile::new`](struct.AtomicFile.html#method.new) and
+/// [`AtomicFile::commit`](struct.AtomicFile.html#method.commit)
+
+pub fn write_atomic (file_path : &path::Path, contents : &[u8])
+  -> Result <(), FileError>
+{
+  use io::Write;
+  let mut file = AtomicFile::new (file_path)?;
+  file.write_all (contents)?;
+  file.commit()?;
+  Ok (())
+}
+
+/// A streaming writer that replaces the target file atomically on
+/// [`commit`](#method.commit).
+///
+/// Data is written to a temporary file in the same directory as the target. Committing
+/// syncs the temporary file, renames it over the target and syncs the directory, so the
+/// replacement survives a crash once `commit` returns. On Unix the temporary file is
+/// created with the mode of the existing target, or `0o600` if there is none (which a
+/// new target keeps), so data written to it is never more widely readable than the
+/// target; the permissions of the target are copied again before the rename. If the
+/// writer is dropped without committing, the temporary file is removed and the target
+/// is left untouched.
+///
+/// Optionally the previous version of the target is kept as a backup, named by an
+/// [`IncrementalNamer`](../incremental/struct.IncrementalNamer.html). The backup is a
+/// hard link to the previous file, so the target exists at every point in time.
+///
+/// # Examples
+///
+/// ```
+/// # use std::io::Write;
+/// # use rs_utils::file::{AtomicFile, IncrementalNamer};
+/// let temp_dir  = tempfile::Builder::new().prefix ("tmp").tempdir().unwrap();
+/// let file_path = temp_dir.path().join ("state.json");
+/// std::fs::write (&file_path, "old").unwrap();
+/// let mut file = AtomicFile::new (&file_path).unwrap();
+/// file.backup (Some (IncrementalNamer::default()));
+/// file.write_all (b"new").unwrap();
+/// assert_eq!(std::fs::read_to_string (&file_path).unwrap(), "old");
+/// let backup = file.commit().unwrap().unwrap();
+/// assert_eq!(backup, temp_dir.path().join ("state.json-0"));
+/// assert_eq!(std::fs::read_to_string (&file_path).unwrap(), "new");
+/// assert_eq!(std::fs::read_to_string (&backup).unwrap(), "old");
+/// ```
+
+#[derive(Debug)]
+pub struct AtomicFile {
+  file_path : path::PathBuf,
+  temp_path : path::PathBuf,
+  file      : fs::File,
+  backup    : Option <IncrementalNamer>,
+  committed : bool
+}
+
+impl AtomicFile {
+  /// Creates a temporary file next to the target, recursively creating parent
+  /// directories.
+  ///
+  /// # Errors
+  ///
+  /// - Not a file (&#x261e; see [`is_file`](../fn.is_file.html))
+  /// - Parent not a directory (&#x261e; see
+  ///   [`ensure_parent_dir`](../classify/fn.ensure_parent_dir.html))
+  /// - I/O errors from reading the permissions of the target, or creating the parent
+  ///   directories or the temporary file
+
+  pub fn new (file_path : &path::Path) -> Result <Self, FileError> {
+    static COUNTER : atomic::AtomicUsize = atomic::AtomicUsize::new (0);
+    if !is_file (file_path)? {
+      return Err (FileError::NotAFile)
+    }
+    ensure_parent_dir (file_path)?;
+    let dir = file_path.parent().unwrap_or_else (|| path::Path::new (""));
+    let file_name = file_path.file_name().ok_or (FileError::NotAFile)?;
+    let mut options = fs::OpenOptions::new();
+    options.write (true).create_new (true);
+    #[cfg(unix)]
+    {
+      use std::os::unix::fs::{OpenOptionsExt, PermissionsExt};
+      let mode = match fs::metadata (file_path) {
+        Ok (metadata) => metadata.permissions().mode() & 0o777,
+        Err (e) if e.kind() == io::ErrorKind::NotFound => 0o600,
+        Err (e) => return Err (e.into())
+      };
+      options.mode (mode);
+    }
+    loop {
+      let mut temp_name = ffi::OsString::from (".");
+      temp_name.push (file_name);
+      temp_name.push (format!(".tmp-{}-{}",
+        process::id(), COUNTER.fetch_add (1, atomic::Ordering::Relaxed)));
+      let temp_path = dir.join (temp_name);
+      match options.open (&temp_path) {
+        Ok (file) => return Ok (AtomicFile {
+          file_path: file_path.to_path_buf(),
+          temp_path,
+          file,
+          backup:    None,
+          committed: false
+        }),
+        Err (e) if e.kind() == io::ErrorKind::AlreadyExists => {}
+        Err (e) => return Err (e.into())
+      }
+    }
+  }
+
+  /// Keep the previous version of the target, if any, as the next incrementally named
+  /// file of the given namer when committing
+  pub fn backup (&mut self, backup : Option <IncrementalNamer>) -> &mut Self {
+    self.backup = backup;
+    self
+  }
+
+  /// Path of the target file
+  pub fn path (&self) -> &path::Path {
+    &self.file_path
+  }
+
+  /// Path of the temporary file holding the data written so far
+  pub fn temp_path (&self) -> &path::Path {
+    &self.temp_path
+  }
+
+  /// Syncs the written data and atomically replaces the target file with it.
+  ///
+  /// Returns the path of the backup of the previous version if a backup namer is set
+  /// and the target existed.
+  ///
+  /// # Errors
+  ///
+  /// - I/O errors from copying the permissions of the target, syncing, linking the
+  ///   backup, or renaming; the target is left untouched and the temporary file and
+  ///   the backup link are removed
+  /// - Index or attempts exhausted when naming the backup
+
+  pub fn commit (mut self) -> Result <Option <path::PathBuf>, FileError> {
+    use io::Write;
+    self.file.flush()?;
+    match fs::metadata (&self.file_path) {
+      Ok (metadata) => self.file.set_permissions (metadata.permissions())?,
+      Err (e) if e.kind() == io::ErrorKind::NotFound => {}
+      Err (e) => return Err (e.into())
+    }
+    self.file.sync_all()?;
+    let backup = match self.backup.as_ref() {
+      Some (namer) if fs::symlink_metadata (&self.file_path).is_ok() => {
+        let (_, backup_path, ()) = namer.create_from (&self.file_path, namer.start,
+          |backup_path| Ok (fs::hard_link (&self.file_path, backup_path)?))?;
+        Some (backup_path)
+      }
+      _ => None
+    };
+    if let Err (e) = fs::rename (&self.temp_path, &self.file_path) {
+      if let Some (backup_path) = backup.as_ref() {
+        let _ = fs::remove_file (backup_path);
+      }
+      return Err (e.into())
+    }
+    self.committed = true;
+    sync_dir (self.file_path.parent().unwrap_or_else (|| path::Path::new ("")))?;
+    Ok (backup)
+  }
+}
+
+impl io::Write for AtomicFile {
+  fn write (&mut self, buf : &[u8]) -> io::Result <usize> {
+    self.file.write (buf)
+  }
+
+  fn flush (&mut self) -> io::Result <()> {
+    self.file.flush()
+  }
+}
+
+impl Drop for AtomicFile {
+  fn drop (&mut self) {
+    if !self.committed {
+      let _ = fs::remove_file (&self.temp_path);
+    }
+  }
+}
+
+/// Syncs a directory so that renames into it are durable
+#[cfg(unix)]
+fn sync_dir (dir : &path::Path) -> Result <(), FileError> {
+  let dir = if dir.as_os_str().is_empty() {
+    path::Path::new (".")
+  } else {
+    dir
+  };
+  fs::File::open (dir)?.sync_all()?;
+  Ok (())
+}
+
+#[cfg(not(unix))]
+#[expect(clippy::unnecessary_wraps)]
+const fn sync_dir (_dir : &path::Path) -> Result <(), FileError> {
+  Ok (())
+}
+
+#[cfg(test)]
+mod tests {
+  use std::io::Write;
+  use super::*;
+
+  #[test]
+  fn drop_without_commit() {
+    let temp_dir  = tempfile::Builder::new().prefix ("tmp").tempdir().unwrap();
+    let file_path = temp_dir.path().join ("somefile");
+    fs::write (&file_path, "old").unwrap();
+    let mut file = AtomicFile::new (&file_path).unwrap();
+    file.write_all (b"partial").unwrap();
+    let temp_path = file.temp_path().to_path_buf();
+    assert!(temp_path.exists());
+    drop (file);
+    assert!(!temp_path.exists());
+    assert_eq!(fs::read_to_string (&file_path).unwrap(), "old");
+    assert_eq!(fs::read_dir (temp_dir.path()).unwrap().count(), 1);
+  }
+
+  #[test]
+  fn backups() {
+    let temp_dir  = tempfile::Builder::new().prefix ("tmp").tempdir().unwrap();
+    let file_path = temp_dir.path().join ("state.json");
+    let namer     = IncrementalNamer::default()
+      .position (crate::file::CounterPosition::BeforeLastExtension).build();
+    for contents in ["0", "1", "2"] {
+      let mut file = AtomicFile::new (&file_path).unwrap();
+      file.backup (Some (namer.clone())).write_all (contents.as_bytes()).unwrap();
+      file.commit().unwrap();
+    }
+    assert_eq!(fs::read_to_string (&file_path).unwrap(), "2");
+    let read = |name : &str| fs::read_to_string (temp_dir.path().join (name)).unwrap();
+    assert_eq!(read ("state-0.json"), "0");
+    assert_eq!(read ("state-1.json"), "1");
+    assert_eq!(fs::read_dir (temp_dir.path()).unwrap().count(), 3);
+  }
+
+  #[cfg(unix)]
+  #[test]
+  fn keeps_permissions() {
+    use std::os::unix::fs::PermissionsExt;
+    let temp_dir  = tempfile::Builder::new().prefix ("tmp").tempdir().unwrap();
+    let file_path = temp_dir.path().join ("secret");
+    let mode = |path : &path::Path|
+      fs::metadata (path).unwrap().permissions().mode() & 0o777;
+    let file = AtomicFile::new (&file_path).unwrap();
+    assert_eq!(mode (file.temp_path()), 0o600);
+    file.commit().unwrap();
+    assert_eq!(mode (&file_path), 0o600);
+    fs::set_permissions (&file_path, fs::Permissions::from_mode (0o640)).unwrap();
+    let mut file = AtomicFile::new (&file_path).unwrap();
+    // the umask may narrow the mode at creation, but never widens it
+    assert_eq!(mode (file.temp_path()) & !0o640, 0);
+    file.write_all (b"new").unwrap();
+    file.commit().unwrap();
+    assert_eq!(mode (&file_path), 0o640);
+    assert_eq!(fs::read_to_string (&file_path).unwrap(), "new");
+  }
+
+  #[test]
+  fn not_a_file() {
+    assert!(matches!(write_atomic (path::Path::new ("somedir/"), b""),
+      Err (FileError::NotAFile)));
+  }
+}
@@ -0,0 +1,226 @@
+//! Filesystem-aware path classification
+
+use std::{fmt, fs, io, path};
+
+use super::FileError;
+
+/// What a path refers to on the filesystem (&#x261e; see
+/// [`classify_path`](fn.classify_path.html))
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum PathKind {
+  /// Nothing exists at the path
+  Missing,
+  File,
+  Dir,
+  /// A symbolic link to an existing target; only returned when not following symlinks
+  Symlink,
+  /// A symbolic link whose target does not exist
+  DanglingSymlink,
+  Fifo,
+  Socket,
+  BlockDevice,
+  CharDevice,
+  /// Any other file type
+  Other
+}
+
+/// Returns what the path refers to, following symbolic links if `follow_symlinks` is
+/// true.
+///
+/// Unlike [`is_file`](../fn.is_file.html), which is purely lexical, this queries the
+/// filesystem. When following symlinks, a link to an existing target is classified as
+/// the target and a link to a missing target as `DanglingSymlink`.
+///
+/// # Examples
+///
+/// ```
+/// # use rs_utils::file::{classify_path, PathKind};
+/// let temp_dir  = tempfile::Builder::new().prefix ("tmp").tempdir().unwrap();
+/// let file_path = temp_dir.path().join ("somefile");
+/// assert_eq!(classify_path (&file_path, true).unwrap(), PathKind::Missing);
+/// std::fs::write (&file_path, "").unwrap();
+/// assert_eq!(classify_path (&file_path, true).unwrap(), PathKind::File);
+/// assert_eq!(classify_path (temp_dir.path(), true).unwrap(), PathKind::Dir);
+/// # #[cfg(unix)] {
+/// let link_path = temp_dir.path().join ("somelink");
+/// std::os::unix::fs::symlink (&file_path, &link_path).unwrap();
+/// assert_eq!(classify_path (&link_path, true).unwrap(), PathKind::File);
+/// assert_eq!(classify_path (&link_path, false).unwrap(), PathKind::Symlink);
+/// std::fs::remove_file (&file_path).unwrap();
+/// assert_eq!(classify_path (&link_path, true).unwrap(), PathKind::DanglingSymlink);
+/// # }
+/// ```
+///
+/// # Errors
+///
+/// I/O errors from querying metadata other than `NotFound`, for example
+/// `NotADirectory` when an ancestor of the path is a file
+
+pub fn classify_path (path : &path::Path, follow_symlinks : bool)
+  -> Result <PathKind, FileError>
+{
+  let metadata = match fs::symlink_metadata (path) {
+    Ok (metadata) => metadata,
+    Err (e) if e.kind() == io::ErrorKind::NotFound => return Ok (PathKind::Missing),
+    Err (e) => return Err (e.into())
+  };
+  if !metadata.file_type().is_symlink() {
+    return Ok (PathKind::from (metadata.file_type()))
+  }
+  match fs::metadata (path) {
+    Ok (_) if !follow_symlinks => Ok (PathKind::Symlink),
+    Ok (target) => Ok (PathKind::from (target.file_type())),
+    Err (e) if e.kind() == io::ErrorKind::NotFound => Ok (PathKind::DanglingSymlink),
+    Err (e) => Err (e.into())
+  }
+}
+
+/// Recursively creates the parent directory of the path, failing with
+/// `ParentNotADirectory` if an existing ancestor is not a directory.
+///
+/// # Examples
+///
+/// ```
+/// # use rs_utils::file::{ensure_parent_dir, FileError};
+/// let temp_dir  = tempfile::Builder::new().prefix ("tmp").tempdir().unwrap();
+/// ensure_parent_dir (&temp_dir.path().join ("a/b/file")).unwrap();
+/// assert!(temp_dir.path().join ("a/b").is_dir());
+/// std::fs::write (temp_dir.path().join ("a/b/file"), "").unwrap();
+/// let e = ensure_parent_dir (&temp_dir.path().join ("a/b/file/c/d")).unwrap_err();
+/// assert!(matches!(&e, FileError::ParentNotADirectory (path)
+///   if *path == temp_dir.path().join ("a/b/file")));
+/// assert_eq!(e.kind(), std::io::ErrorKind::NotADirectory);
+/// ```
+///
+/// # Errors
+///
+/// - Parent not a directory
+/// - I/O errors from querying metadata or creating directories
+
+pub fn ensure_parent_dir (path : &path::Path) -> Result <(), FileError> {
+  let Some (parent) = path.parent().filter (|parent| !parent.as_os_str().is_empty())
+  else {
+    return Ok (())
+  };
+  for ancestor in parent.ancestors().filter (|a| !a.as_os_str().is_empty()) {
+    match classify_path (ancestor, true) {
+      Ok (PathKind::Dir) if ancestor == parent => return Ok (()),
+      Ok (PathKind::Dir) => break,
+      Ok (PathKind::Missing) => {}
+      Err (FileError::Io (e)) if e.kind() == io::ErrorKind::NotADirectory => {}
+      Ok (_) => return Err (FileError::ParentNotADirectory (ancestor.to_path_buf())),
+      Err (e) => return Err (e)
+    }
+  }
+  fs::create_dir_all (parent)?;
+  Ok (())
+}
+
+/// Fails with `Exists` if anything, including a dangling symlink, exists at the path.
+///
+/// # Examples
+///
+/// ```
+/// # use rs_utils::file::{ensure_file_absent, FileError, PathKind};
+/// let temp_dir = tempfile::Builder::new().prefix ("tmp").tempdir().unwrap();
+/// ensure_file_absent (&temp_dir.path().join ("somefile")).unwrap();
+/// let e = ensure_file_absent (temp_dir.path()).unwrap_err();
+/// assert!(matches!(e, FileError::Exists (PathKind::Dir)));
+/// assert_eq!(e.to_string(), "path exists: directory");
+/// ```
+///
+/// # Errors
+///
+/// - Exists, with the kind of the existing path
+/// - I/O errors from querying metadata
+
+pub fn ensure_file_absent (path : &path::Path) -> Result <(), FileError> {
+  match classify_path (path, false)? {
+    PathKind::Missing => Ok (()),
+    kind => Err (FileError::Exists (kind))
+  }
+}
+
+impl From <fs::FileType> for PathKind {
+  fn from (file_type : fs::FileType) -> Self {
+    #[cfg(unix)]
+    use std::os::unix::fs::FileTypeExt;
+    if file_type.is_file() {
+      return PathKind::File
+    }
+    if file_type.is_dir() {
+      return PathKind::Dir
+    }
+    if file_type.is_symlink() {
+      return PathKind::Symlink
+    }
+    #[cfg(unix)]
+    {
+      if file_type.is_fifo() {
+        return PathKind::Fifo
+      }
+      if file_type.is_socket() {
+        return PathKind::Socket
+      }
+      if file_type.is_block_device() {
+        return PathKind::BlockDevice
+      }
+      if file_type.is_char_device() {
+        return PathKind::CharDevice
+      }
+    }
+    PathKind::Other
+  }
+}
+
+impl fmt::Display for PathKind {
+  fn fmt (&self, f : &mut fmt::Formatter) -> fmt::Result {
+    match self {
+      PathKind::Missing         => write!(f, "missing"),
+      PathKind::File            => write!(f, "regular file"),
+      PathKind::Dir             => write!(f, "directory"),
+      PathKind::Symlink         => write!(f, "symlink"),
+      PathKind::DanglingSymlink => write!(f, "dangling symlink"),
+      PathKind::Fifo            => write!(f, "FIFO"),
+      PathKind::Socket          => write!(f, "socket"),
+      PathKind::BlockDevice     => write!(f, "block device"),
+      PathKind::CharDevice      => write!(f, "character device"),
+      PathKind::Other           => write!(f, "other")
+    }
+  }
+}
+
+#[cfg(all(test, target_os = "linux"))]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn special_files() {
+    let temp_dir    = tempfile::Builder::new().prefix ("tmp").tempdir().unwrap();
+    let socket_path = temp_dir.path().join ("socket");
+    let _listener   = std::os::unix::net::UnixListener::bind (&socket_path).unwrap();
+    assert_eq!(classify_path (&socket_path, false).unwrap(), PathKind::Socket);
+    assert_eq!(classify_path (path::Path::new ("/dev/null"), true).unwrap(),
+      PathKind::CharDevice);
+    let fifo_path = temp_dir.path().join ("fifo");
+    if std::process::Command::new ("mkfifo").arg (&fifo_path).status()
+      .is_ok_and (|status| status.success())
+    {
+      assert_eq!(classify_path (&fifo_path, true).unwrap(), PathKind::Fifo);
+      assert!(matches!(ensure_file_absent (&fifo_path),
+        Err (FileError::Exists (PathKind::Fifo))));
+    }
+  }
+
+  #[test]
+  fn dangling_symlinks() {
+    let temp_dir  = tempfile::Builder::new().prefix ("tmp").tempdir().unwrap();
+    let link_path = temp_dir.path().join ("link");
+    std::os::unix::fs::symlink (temp_dir.path().join ("missing"), &link_path).unwrap();
+    assert_eq!(classify_path (&link_path, false).unwrap(), PathKind::DanglingSymlink);
+    assert!(matches!(ensure_file_absent (&link_path),
+      Err (FileError::Exists (PathKind::DanglingSymlink))));
+    assert!(matches!(ensure_parent_dir (&link_path.join ("file")),
+      Err (FileError::ParentNotADirectory (path)) if path == link_path));
+  }
+}
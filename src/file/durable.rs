@@ -0,0 +1,210 @@
+//! Durability policies for append files
+
+use std::{fmt, fs, io, time};
+
+use super::write_fmt_record;
+
+/// When written data is synced to disk with `fs::File::sync_data`
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub enum SyncPolicy {
+  /// Leave syncing to the operating system
+  #[default]
+  Never,
+  /// Sync after a write once at least this many bytes are unsynced
+  Bytes (u64),
+  /// Sync after every this many writes
+  Writes (u64),
+  /// Sync after a write once the last sync is at least this long ago; there is no
+  /// background timer, so data written before an idle period stays unsynced until the
+  /// next write or [`sync_now`](struct.DurableFile.html#method.sync_now)
+  Interval (time::Duration),
+  /// Sync after every write
+  Always
+}
+
+/// An append file wrapper that syncs written data according to a
+/// [`SyncPolicy`](enum.SyncPolicy.html).
+///
+/// Each call to `write` is treated as one record and is written in full with
+/// `write_all` before the policy is checked, so `Writes (n)` counts records and a
+/// `write!` or `writeln!` call is never synced halfway. Unless the policy is `Never`,
+/// unsynced data is synced when the wrapper is dropped, ignoring errors; call
+/// [`sync_now`](#method.sync_now) first to observe them.
+///
+/// Files created by the incremental helpers can be wrapped directly;
+/// [`RotatingFileWriter`](../rotate/struct.RotatingFileWriter.html) takes a policy with
+/// [`sync_policy`](../rotate/struct.RotatingFileWriter.html#method.sync_policy).
+///
+/// # Examples
+///
+/// ```
+/// # use std::io::Write;
+/// # use rs_utils::file::{file_new_append_incremental, DurableFile, SyncPolicy};
+/// let temp_dir  = tempfile::Builder::new().prefix ("tmp").tempdir().unwrap();
+/// let file_path = temp_dir.path().join ("crash.log");
+/// let (_, file) = file_new_append_incremental (&file_path).unwrap();
+/// let mut file  = DurableFile::new (file, SyncPolicy::Writes (2));
+/// file.write_all (b"a\n").unwrap();
+/// assert_eq!(file.unsynced_bytes(), 2);
+/// file.write_all (b"b\n").unwrap();
+/// assert_eq!(file.unsynced_bytes(), 0);
+/// ```
+
+#[derive(Debug)]
+pub struct DurableFile {
+  file  : fs::File,
+  state : SyncState
+}
+
+/// Unsynced data accounting shared by the writers that take a `SyncPolicy`
+#[derive(Clone, Debug)]
+pub(crate) struct SyncState {
+  policy : SyncPolicy,
+  bytes  : u64,
+  writes : u64,
+  synced : time::Instant
+}
+
+impl DurableFile {
+  pub fn new (file : fs::File, policy : SyncPolicy) -> Self {
+    DurableFile { file, state: SyncState::new (policy) }
+  }
+
+  pub const fn policy (&self) -> SyncPolicy {
+    self.state.policy
+  }
+
+  pub const fn set_policy (&mut self, policy : SyncPolicy) -> &mut Self {
+    self.state.policy = policy;
+    self
+  }
+
+  pub const fn file (&self) -> &fs::File {
+    &self.file
+  }
+
+  /// Number of bytes written since the last sync
+  pub const fn unsynced_bytes (&self) -> u64 {
+    self.state.bytes
+  }
+
+  /// Syncs all written data to disk regardless of the policy.
+  ///
+  /// # Errors
+  ///
+  /// I/O errors from syncing
+
+  pub fn sync_now (&mut self) -> io::Result <()> {
+    self.state.sync (&self.file)
+  }
+}
+
+impl io::Write for DurableFile {
+  fn write (&mut self, buf : &[u8]) -> io::Result <usize> {
+    self.file.write_all (buf)?;
+    self.state.written (&self.file, buf.len())?;
+    Ok (buf.len())
+  }
+
+  fn write_fmt (&mut self, args : fmt::Arguments) -> io::Result <()> {
+    write_fmt_record (self, args)
+  }
+
+  fn flush (&mut self) -> io::Result <()> {
+    self.file.flush()
+  }
+}
+
+impl Drop for DurableFile {
+  fn drop (&mut self) {
+    let _ = self.state.finish (&self.file);
+  }
+}
+
+impl SyncState {
+  pub(crate) fn new (policy : SyncPolicy) -> Self {
+    SyncState { policy, bytes: 0, writes: 0, synced: time::Instant::now() }
+  }
+
+  pub(crate) const fn policy (&self) -> SyncPolicy {
+    self.policy
+  }
+
+  pub(crate) const fn set_policy (&mut self, policy : SyncPolicy) {
+    self.policy = policy;
+  }
+
+  /// Accounts for a write of `len` bytes to `file` and syncs if the policy is due
+  pub(crate) fn written (&mut self, file : &fs::File, len : usize) -> io::Result <()> {
+    self.bytes  = self.bytes.saturating_add (len as u64);
+    self.writes = self.writes.saturating_add (1);
+    let due = match self.policy {
+      SyncPolicy::Never          => false,
+      SyncPolicy::Bytes (max)    => self.bytes >= max,
+      SyncPolicy::Writes (max)   => self.writes >= max,
+      SyncPolicy::Interval (max) => self.synced.elapsed() >= max,
+      SyncPolicy::Always         => true
+    };
+    if due {
+      self.sync (file)?;
+    }
+    Ok (())
+  }
+
+  /// Syncs `file` before it is closed unless the policy is `Never`
+  pub(crate) fn finish (&mut self, file : &fs::File) -> io::Result <()> {
+    if self.policy == SyncPolicy::Never {
+      return Ok (())
+    }
+    self.sync (file)
+  }
+
+  /// Syncs `file` if anything was written since the last sync
+  pub(crate) fn sync (&mut self, file : &fs::File) -> io::Result <()> {
+    if self.writes > 0 {
+      file.sync_data()?;
+      self.bytes  = 0;
+      self.writes = 0;
+    }
+    self.synced = time::Instant::now();
+    Ok (())
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use std::io::Write;
+  use super::*;
+
+  #[test]
+  fn policies() {
+    let temp_dir = tempfile::Builder::new().prefix ("tmp").tempdir().unwrap();
+    let unsynced = |policy, writes : &[&[u8]]| {
+      let file = fs::File::create (temp_dir.path().join ("file")).unwrap();
+      let mut file = DurableFile::new (file, policy);
+      for buf in writes {
+        file.write_all (buf).unwrap();
+      }
+      file.unsynced_bytes()
+    };
+    assert_eq!(unsynced (SyncPolicy::Never, &[b"abc", b"def"]), 6);
+    assert_eq!(unsynced (SyncPolicy::Always, &[b"abc", b"def"]), 0);
+    assert_eq!(unsynced (SyncPolicy::Bytes (4), &[b"abc", b"def", b"g"]), 1);
+    assert_eq!(unsynced (SyncPolicy::Writes (2), &[b"abc", b"def", b"g"]), 1);
+    assert_eq!(unsynced (SyncPolicy::Interval (time::Duration::ZERO), &[b"abc"]), 0);
+    assert_eq!(
+      unsynced (SyncPolicy::Interval (time::Duration::from_secs (3600)), &[b"abc"]), 3);
+  }
+
+  #[test]
+  fn formatted_records() {
+    let temp_dir = tempfile::Builder::new().prefix ("tmp").tempdir().unwrap();
+    let file     = fs::File::create (temp_dir.path().join ("file")).unwrap();
+    let mut file = DurableFile::new (file, SyncPolicy::Writes (2));
+    let x = 1;
+    writeln!(file, "a {x} b").unwrap();
+    assert_eq!(file.unsynced_bytes(), 6);
+    writeln!(file, "c {x} d").unwrap();
+    assert_eq!(file.unsynced_bytes(), 0);
+  }
+}
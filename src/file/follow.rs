@@ -0,0 +1,517 @@
+//! Following files as they are appended, like `tail -f`
+
+use std::{fs, io, path, thread, time};
+use std::io::{BufRead, Seek};
+use std::ops::ControlFlow;
+
+use super::{is_file, FileError, IncrementalNamer};
+
+/// How a [`FileFollower`](struct.FileFollower.html) waits for new data
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum FollowWait {
+  /// Check the file again after sleeping this long
+  Poll (time::Duration),
+  /// Wake up on inotify events for the directory of the file, checking again after
+  /// this long if no event arrives. Falls back to polling if inotify is not available.
+  #[cfg(target_os = "linux")]
+  #[cfg_attr(docsrs, doc(cfg(target_os = "linux")))]
+  Inotify (time::Duration)
+}
+
+/// Reads complete lines from a file as they are appended, like `tail -f`.
+///
+/// A follower created with [`new`](#method.new) follows a single path. If the file is
+/// truncated, reading restarts from its beginning; on Unix, if the path is replaced by
+/// a different file (e.g. moved away and recreated), the old file is read to its end
+/// and the new file is followed from its beginning. A follower created with
+/// [`series`](#method.series) follows the latest member of an incremental series and
+/// moves on to the next higher index once one appears, as written by
+/// [`RotatingFileWriter`](../rotate/struct.RotatingFileWriter.html); indices that are
+/// skipped or already removed are passed over. A missing file is waited for.
+///
+/// The file is read one line at a time, so following a large existing file from its
+/// beginning only buffers the line being returned.
+///
+/// Lines are returned without the line terminator (`\n` or `\r\n`), with invalid UTF-8
+/// replaced. An unterminated last line is only returned when the follower moves on to
+/// another file; it is discarded on truncation.
+///
+/// Lines can be taken without blocking with [`try_next_line`](#method.try_next_line),
+/// passed to a callback with [`follow`](#method.follow), or iterated over: the iterator
+/// blocks until the next line and never ends.
+///
+/// # Examples
+///
+/// ```
+/// # use std::io::Write;
+/// # use rs_utils::file::{file_new_append, FileFollower};
+/// let temp_dir  = tempfile::Builder::new().prefix ("tmp").tempdir().unwrap();
+/// let file_path = temp_dir.path().join ("app.log");
+/// let mut follower = FileFollower::new (&file_path).unwrap();
+/// assert_eq!(follower.try_next_line().unwrap(), None);
+/// let mut file = file_new_append (&file_path).unwrap();
+/// file.write_all (b"started\nworking").unwrap();
+/// assert_eq!(follower.next().unwrap().unwrap(), "started");
+/// assert_eq!(follower.try_next_line().unwrap(), None);
+/// file.write_all (b"\n").unwrap();
+/// assert_eq!(follower.try_next_line().unwrap().as_deref(), Some ("working"));
+/// ```
+
+#[derive(Debug)]
+pub struct FileFollower {
+  file_path : path::PathBuf,
+  namer     : Option <IncrementalNamer>,
+  wait      : FollowWait,
+  from_end  : bool,
+  path      : path::PathBuf,
+  index     : usize,
+  file      : Option <io::BufReader <fs::File>>,
+  offset    : u64,
+  partial   : Vec <u8>,
+  #[cfg(target_os = "linux")]
+  inotify   : Option <inotify::Watch>
+}
+
+impl Default for FollowWait {
+  fn default() -> Self {
+    FollowWait::Poll (time::Duration::from_millis (100))
+  }
+}
+
+impl FileFollower {
+  /// Follows the file at the given path from its beginning.
+  ///
+  /// # Errors
+  ///
+  /// - Not a file
+
+  pub fn new (file_path : &path::Path) -> Result <Self, FileError> {
+    if !is_file (file_path)? {
+      return Err (FileError::NotAFile)
+    }
+    Ok (Self::with_namer (file_path, None))
+  }
+
+  /// Follows the incremental series of `file_path` named by `namer`, starting from the
+  /// beginning of its latest member.
+  ///
+  /// # Errors
+  ///
+  /// - Not a file
+
+  pub fn series (file_path : &path::Path, namer : IncrementalNamer)
+    -> Result <Self, FileError>
+  {
+    if !is_file (file_path)? {
+      return Err (FileError::NotAFile)
+    }
+    Ok (Self::with_namer (file_path, Some (namer)))
+  }
+
+  pub const fn wait (&mut self, wait : FollowWait) -> &mut Self {
+    self.wait = wait;
+    self
+  }
+
+  /// Whether the first file is followed from its end instead of its beginning; files
+  /// reached by rotation or replacement are always read from the beginning
+  pub const fn from_end (&mut self, from_end : bool) -> &mut Self {
+    self.from_end = from_end;
+    self
+  }
+
+  /// Path of the file currently followed; for a series, this is the unnumbered path
+  /// until the first member is found
+  pub fn path (&self) -> &path::Path {
+    &self.path
+  }
+
+  /// Offset up to which the current file has been read
+  pub const fn offset (&self) -> u64 {
+    self.offset
+  }
+
+  /// Returns the next complete line if one is available, without waiting.
+  ///
+  /// # Errors
+  ///
+  /// - Errors from [`IncrementalNamer::latest`](
+  ///   ../incremental/struct.IncrementalNamer.html#method.latest) when following a
+  ///   series
+  /// - I/O errors from reading the file
+
+  pub fn try_next_line (&mut self) -> Result <Option <String>, FileError> {
+    loop {
+      if let Some (line) = self.take_line() {
+        return Ok (Some (line))
+      }
+      if self.file.is_none() && !self.open_first()? {
+        return Ok (None)
+      }
+      if self.read_line()? == 0 && !self.check_switch()? {
+        return Ok (None)
+      }
+    }
+  }
+
+  /// Passes each line to the callback as it is appended, waiting for new lines until
+  /// the callback breaks.
+  ///
+  /// # Errors
+  ///
+  /// &#x261e; See [`try_next_line`](#method.try_next_line)
+
+  pub fn follow <F> (&mut self, mut f : F) -> Result <(), FileError> where
+    F : FnMut (&str) -> ControlFlow <()>
+  {
+    loop {
+      match self.try_next_line()? {
+        Some (line) => if f (&line).is_break() {
+          return Ok (())
+        }
+        None => self.wait_for_change()
+      }
+    }
+  }
+
+  fn with_namer (file_path : &path::Path, namer : Option <IncrementalNamer>) -> Self {
+    FileFollower {
+      file_path: file_path.to_path_buf(),
+      namer,
+      wait:      FollowWait::default(),
+      from_end:  false,
+      path:      file_path.to_path_buf(),
+      index:     0,
+      file:      None,
+      offset:    0,
+      partial:   Vec::new(),
+      #[cfg(target_os = "linux")]
+      inotify:   None
+    }
+  }
+
+  /// Removes and returns the first complete line of the buffer
+  fn take_line (&mut self) -> Option <String> {
+    let end = self.partial.iter().position (|b| *b == b'\n')?;
+    let line = self.partial.drain (..=end).collect::<Vec <_>>();
+    Some (line_to_string (&line))
+  }
+
+  /// Opens the first file, returning false if it does not exist yet
+  fn open_first (&mut self) -> Result <bool, FileError> {
+    if let Some (namer) = self.namer.as_ref() {
+      match namer.latest (&self.file_path)? {
+        Some ((index, path)) => {
+          self.index = index;
+          self.path  = path;
+        }
+        None => return Ok (false)
+      }
+    }
+    let mut file = match fs::File::open (&self.path) {
+      Ok (file) => file,
+      Err (e) if e.kind() == io::ErrorKind::NotFound => return Ok (false),
+      Err (e) => return Err (e.into())
+    };
+    self.offset = if self.from_end {
+      file.seek (io::SeekFrom::End (0))?
+    } else {
+      0
+    };
+    self.file = Some (io::BufReader::new (file));
+    Ok (true)
+  }
+
+  /// Appends the current file to the buffer up to and including the next line
+  /// terminator, or up to its end if there is none, and returns the number of bytes
+  /// read
+  fn read_line (&mut self) -> Result <u64, FileError> {
+    let Some (file) = self.file.as_mut() else {
+      return Ok (0)
+    };
+    let read = file.read_until (b'\n', &mut self.partial)? as u64;
+    self.offset += read;
+    Ok (read)
+  }
+
+  /// Checks for truncation, replacement and rotation at the end of the current file,
+  /// returning true if reading should continue
+  fn check_switch (&mut self) -> Result <bool, FileError> {
+    let Some (file) = self.file.as_mut() else {
+      return Ok (false)
+    };
+    if file.get_ref().metadata()?.len() < self.offset {
+      file.rewind()?;
+      self.offset = 0;
+      self.partial.clear();
+      return Ok (true)
+    }
+    let next = match self.namer.as_ref() {
+      Some (namer) => namer.series (&self.file_path)?.into_iter()
+        .find (|(index, _)| *index > self.index),
+      None => None
+    };
+    let next = match next {
+      Some (next) => Some (next),
+      None if self.replaced()? => Some ((self.index, self.path.clone())),
+      None => None
+    };
+    let Some ((index, path)) = next else {
+      return Ok (false)
+    };
+    // data appended to the current file before it was left is read first
+    if self.read_line()? == 0 {
+      self.switch (index, path)?;
+    }
+    Ok (true)
+  }
+
+  /// Whether the followed path now refers to a different file than the open one
+  #[cfg(unix)]
+  fn replaced (&self) -> Result <bool, FileError> {
+    use std::os::unix::fs::MetadataExt;
+    let Some (file) = self.file.as_ref() else {
+      return Ok (false)
+    };
+    let metadata = match fs::metadata (&self.path) {
+      Ok (metadata) => metadata,
+      Err (e) if e.kind() == io::ErrorKind::NotFound => return Ok (false),
+      Err (e) => return Err (e.into())
+    };
+    let open = file.get_ref().metadata()?;
+    Ok ((metadata.dev(), metadata.ino()) != (open.dev(), open.ino()))
+  }
+
+  #[cfg(not(unix))]
+  #[expect(clippy::unnecessary_wraps)]
+  #[expect(clippy::unused_self)]
+  const fn replaced (&self) -> Result <bool, FileError> {
+    Ok (false)
+  }
+
+  /// Emits the unterminated rest of the current file as a line and opens `path` from
+  /// its beginning
+  fn switch (&mut self, index : usize, path : path::PathBuf) -> Result <(), FileError> {
+    let file = match fs::File::open (&path) {
+      Ok (file) => Some (io::BufReader::new (file)),
+      Err (e) if e.kind() == io::ErrorKind::NotFound => None,
+      Err (e) => return Err (e.into())
+    };
+    if !self.partial.is_empty() {
+      self.partial.push (b'\n');
+    }
+    self.index  = index;
+    self.path   = path;
+    self.file   = file;
+    self.offset = 0;
+    Ok (())
+  }
+
+  fn wait_for_change (&mut self) {
+    match self.wait {
+      FollowWait::Poll (interval) => thread::sleep (interval),
+      #[cfg(target_os = "linux")]
+      FollowWait::Inotify (timeout) => {
+        if self.inotify.is_none() {
+          let dir = self.file_path.parent()
+            .filter (|dir| !dir.as_os_str().is_empty())
+            .unwrap_or_else (|| path::Path::new ("."));
+          self.inotify = inotify::Watch::new (dir).ok();
+        }
+        match self.inotify.as_mut() {
+          Some (watch) => if watch.wait (timeout).is_err() {
+            thread::sleep (timeout);
+          }
+          None => thread::sleep (timeout)
+        }
+      }
+    }
+  }
+}
+
+impl Iterator for FileFollower {
+  type Item = Result <String, FileError>;
+
+  /// Blocks until the next line is available; never returns `None`
+  fn next (&mut self) -> Option <Self::Item> {
+    loop {
+      match self.try_next_line() {
+        Ok (Some (line)) => return Some (Ok (line)),
+        Ok (None) => self.wait_for_change(),
+        Err (e) => return Some (Err (e))
+      }
+    }
+  }
+}
+
+fn line_to_string (line : &[u8]) -> String {
+  let line = line.strip_suffix (b"\n").unwrap_or (line);
+  let line = line.strip_suffix (b"\r").unwrap_or (line);
+  String::from_utf8_lossy (line).into_owned()
+}
+
+#[cfg(target_os = "linux")]
+mod inotify {
+  use std::{ffi, fs, io, path, time};
+  use std::io::Read;
+  use std::os::fd::{AsRawFd, FromRawFd, OwnedFd};
+  use std::os::unix::ffi::OsStrExt;
+
+  /// An inotify instance watching one directory for changes to its entries
+  #[derive(Debug)]
+  pub(super) struct Watch {
+    file : fs::File
+  }
+
+  impl Watch {
+    pub(super) fn new (dir : &path::Path) -> io::Result <Self> {
+      let dir = ffi::CString::new (dir.as_os_str().as_bytes())
+        .map_err (|_| io::Error::from (io::ErrorKind::InvalidInput))?;
+      // SAFETY: inotify_init1 takes no pointers; a non-negative result is a new file
+      // descriptor owned by nobody else
+      let fd = unsafe { libc::inotify_init1 (libc::IN_NONBLOCK | libc::IN_CLOEXEC) };
+      if fd < 0 {
+        return Err (io::Error::last_os_error())
+      }
+      // SAFETY: see above
+      let fd = unsafe { OwnedFd::from_raw_fd (fd) };
+      let mask = libc::IN_MODIFY | libc::IN_ATTRIB | libc::IN_CLOSE_WRITE |
+        libc::IN_MOVED_FROM | libc::IN_MOVED_TO | libc::IN_CREATE | libc::IN_DELETE;
+      // SAFETY: the path is a valid NUL-terminated string that outlives the call
+      if unsafe { libc::inotify_add_watch (fd.as_raw_fd(), dir.as_ptr(), mask) } < 0 {
+        return Err (io::Error::last_os_error())
+      }
+      Ok (Watch { file: fs::File::from (fd) })
+    }
+
+    /// Waits until an event arrives or the timeout expires, then discards pending
+    /// events
+    pub(super) fn wait (&mut self, timeout : time::Duration) -> io::Result <()> {
+      let mut fds = libc::pollfd {
+        fd: self.file.as_raw_fd(), events: libc::POLLIN, revents: 0
+      };
+      let timeout = i32::try_from (timeout.as_millis()).unwrap_or (i32::MAX);
+      // SAFETY: `fds` points to one valid pollfd for the duration of the call
+      if unsafe { libc::poll (&raw mut fds, 1, timeout) } < 0 {
+        let e = io::Error::last_os_error();
+        if e.kind() != io::ErrorKind::Interrupted {
+          return Err (e)
+        }
+      }
+      let mut buf = [0u8; 4096];
+      loop {
+        match self.file.read (&mut buf) {
+          Ok (0) => return Ok (()),
+          Ok (_) => {}
+          Err (e) if e.kind() == io::ErrorKind::WouldBlock => return Ok (()),
+          Err (e) => return Err (e)
+        }
+      }
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use std::io::Write;
+  use crate::file::{file_new_append, RotatingFileWriter, RotationPolicy};
+  use super::*;
+
+  fn drain (follower : &mut FileFollower) -> Vec <String> {
+    std::iter::from_fn (|| follower.try_next_line().unwrap()).collect()
+  }
+
+  #[test]
+  fn truncation_and_replacement() {
+    let temp_dir  = tempfile::Builder::new().prefix ("tmp").tempdir().unwrap();
+    let file_path = temp_dir.path().join ("log");
+    let mut file  = file_new_append (&file_path).unwrap();
+    file.write_all (b"a\r\nb\npartial").unwrap();
+    let mut follower = FileFollower::new (&file_path).unwrap();
+    assert_eq!(drain (&mut follower), ["a", "b"]);
+    file.set_len (0).unwrap();
+    file.write_all (b"c\n").unwrap();
+    assert_eq!(drain (&mut follower), ["c"]);
+    file.write_all (b"unterminated").unwrap();
+    fs::rename (&file_path, temp_dir.path().join ("log.old")).unwrap();
+    assert_eq!(drain (&mut follower), Vec::<String>::new());
+    let mut file = file_new_append (&file_path).unwrap();
+    file.write_all (b"d\n").unwrap();
+    #[cfg(unix)]
+    assert_eq!(drain (&mut follower), ["unterminated", "d"]);
+    let mut follower = FileFollower::new (&file_path).unwrap();
+    follower.from_end (true);
+    assert_eq!(drain (&mut follower), Vec::<String>::new());
+    file.write_all (b"e\n").unwrap();
+    assert_eq!(drain (&mut follower), ["e"]);
+  }
+
+  #[test]
+  fn rotating_series() {
+    let temp_dir  = tempfile::Builder::new().prefix ("tmp").tempdir().unwrap();
+    let file_path = temp_dir.path().join ("log");
+    let mut follower = FileFollower::series (&file_path, IncrementalNamer::default())
+      .unwrap();
+    assert_eq!(follower.try_next_line().unwrap(), None);
+    let mut writer = RotatingFileWriter::new (&file_path, IncrementalNamer::default(),
+      RotationPolicy::default().max_records (Some (2)).build()).unwrap();
+    writer.write_all (b"0\n").unwrap();
+    assert_eq!(drain (&mut follower), ["0"]);
+    for i in 1..5 {
+      writer.write_all (format!("{i}\n").as_bytes()).unwrap();
+    }
+    assert_eq!(drain (&mut follower), ["1", "2", "3", "4"]);
+    assert_eq!(follower.path(), temp_dir.path().join ("log-2"));
+    drop (writer);
+    fs::write (temp_dir.path().join ("log-4"), "skipped\n").unwrap();
+    assert_eq!(drain (&mut follower), ["skipped"]);
+    assert_eq!(follower.path(), temp_dir.path().join ("log-4"));
+  }
+
+  #[test]
+  fn reads_line_by_line() {
+    let temp_dir  = tempfile::Builder::new().prefix ("tmp").tempdir().unwrap();
+    let file_path = temp_dir.path().join ("log");
+    let lines = (0..10_000).map (|i| i.to_string() + "\n").collect::<String>();
+    fs::write (&file_path, &lines).unwrap();
+    let mut follower = FileFollower::new (&file_path).unwrap();
+    assert_eq!(follower.try_next_line().unwrap().as_deref(), Some ("0"));
+    assert_eq!(follower.offset(), 2);
+    assert!(follower.partial.is_empty());
+    assert_eq!(drain (&mut follower).len(), 9_999);
+    assert_eq!(follower.offset(), lines.len() as u64);
+  }
+
+  #[test]
+  fn follow_callback() {
+    let temp_dir  = tempfile::Builder::new().prefix ("tmp").tempdir().unwrap();
+    let file_path = temp_dir.path().join ("log");
+    let waits = [
+      FollowWait::Poll (time::Duration::from_millis (5)),
+      #[cfg(target_os = "linux")]
+      FollowWait::Inotify (time::Duration::from_secs (5))
+    ];
+    for (i, wait) in waits.into_iter().enumerate() {
+      let file_path = file_path.with_extension (i.to_string());
+      let writer_path = file_path.clone();
+      let writer = thread::spawn (move || {
+        let mut file = file_new_append (&writer_path).unwrap();
+        for line in 0..3 {
+          thread::sleep (time::Duration::from_millis (20));
+          file.write_all (format!("{line}\n").as_bytes()).unwrap();
+        }
+      });
+      let mut lines = Vec::new();
+      FileFollower::new (&file_path).unwrap().wait (wait).follow (|line| {
+        lines.push (line.to_string());
+        if lines.len() == 3 {
+          ControlFlow::Break (())
+        } else {
+          ControlFlow::Continue (())
+        }
+      }).unwrap();
+      assert_eq!(lines, ["0", "1", "2"]);
+      writer.join().unwrap();
+    }
+  }
+}
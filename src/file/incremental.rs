@@ -0,0 +1,890 @@
+//! Configurable incremental file naming
+#![expect(clippy::module_name_repetitions)]
+
+use std::{collections, ffi, fs, io, path};
+
+use super::{
+  ensure_parent_dir, file_new_append, is_file, FileError, FileOptions,
+  INCREMENTAL_CREATE_ATTEMPTS, INCREMENTAL_MAX_INDEX
+};
+
+/// Where the counter is inserted into the file name
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub enum CounterPosition {
+  /// `name.tar.gz` &#x2192; `name.tar.gz-N`
+  #[default]
+  AfterName,
+  /// `name.tar.gz` &#x2192; `name-N.tar.gz`
+  BeforeFirstExtension,
+  /// `name.tar.gz` &#x2192; `name.tar-N.gz`
+  BeforeLastExtension,
+  /// `name.tar.gz` &#x2192; `name-N.tar.gz`, `name.v1.txt` &#x2192; `name.v1-N.txt`:
+  /// before the longest extension in the namer's `extensions` list that ends the file
+  /// name, otherwise before the last extension
+  BeforeKnownExtension,
+  /// `name.tar.gz` &#x2192; `N-name.tar.gz`
+  Prefix
+}
+
+/// The parts of a file name before and after the counter
+type NameParts = (ffi::OsString, ffi::OsString);
+
+/// How the next available index is found
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub enum IndexLookup {
+  /// Check whether each candidate path exists, counting up from the start index; errors
+  /// from the check, such as permission errors, are returned rather than treated as an
+  /// existing path
+  #[default]
+  Probe,
+  /// Read the parent directory once and return one past the largest existing index
+  ScanAfterMax,
+  /// Read the parent directory once and return the first index that is not taken
+  ScanFirstGap
+}
+
+/// Compound extensions recognized by default with
+/// `CounterPosition::BeforeKnownExtension`
+pub const COMPOUND_EXTENSIONS : &[&str] = &[
+  "tar.gz", "tar.bz2", "tar.xz", "tar.zst", "tar.lz4", "tar.lzma",
+  "log.gz", "log.bz2", "log.xz", "log.zst",
+  "json.gz", "jsonl.gz", "csv.gz", "txt.gz"
+];
+
+/// Builder for incremental file naming schemes.
+///
+/// The counter is written as `<separator><N><terminator>` when it is a suffix, and as
+/// `<N><separator>` when it is a prefix. `N` starts from `start` and is left-padded
+/// with zeros to `pad_width` digits. Positions that insert the counter before an
+/// extension fall back to `AfterName` when the file name has no extension. Leading dots
+/// of hidden files are part of the name, so `.env` has no extension and `.env.local`
+/// has the extension `local`.
+///
+/// The `extensions` list used by `BeforeKnownExtension` holds extensions without the
+/// leading dot and is matched case-insensitively. To state the extension of a file
+/// name explicitly, set the list to just that extension.
+///
+/// By default each candidate path is probed with `Path::try_exists`; for large series
+/// in a single directory, an [`IndexLookup`](enum.IndexLookup.html) scan mode reads the
+/// directory once instead. Scanning only recognizes names in the exact form produced by
+/// the namer, so other files in the directory are ignored.
+///
+/// The default namer produces the `-N` suffix used by
+/// [`file_path_incremental`](../fn.file_path_incremental.html), with indices up to
+/// [`INCREMENTAL_MAX_INDEX`](../constant.INCREMENTAL_MAX_INDEX.html).
+///
+/// # Examples
+///
+/// ```
+/// # use std::path::Path;
+/// # use rs_utils::file::{CounterPosition, IncrementalNamer};
+/// let namer = IncrementalNamer::default()
+///   .separator ("_")
+///   .pad_width (4)
+///   .position (CounterPosition::BeforeLastExtension)
+///   .build();
+/// assert_eq!(
+///   namer.path_for_index (Path::new ("somedir/run.log"), 7).unwrap(),
+///   Path::new ("somedir/run_0007.log"));
+///
+/// let namer = IncrementalNamer::default()
+///   .separator ("(")
+///   .terminator (")")
+///   .start (2)
+///   .position (CounterPosition::BeforeLastExtension)
+///   .build();
+/// assert_eq!(
+///   namer.next_path (Path::new ("somedir/out.txt")).unwrap(),
+///   Path::new ("somedir/out(2).txt"));
+/// ```
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct IncrementalNamer {
+  pub separator  : String,
+  pub terminator : String,
+  pub start      : usize,
+  pub pad_width  : usize,
+  pub position   : CounterPosition,
+  pub max_index  : Option <usize>,
+  pub lookup     : IndexLookup,
+  pub attempts   : usize,
+  pub extensions : Vec <String>
+}
+
+impl IncrementalNamer {
+  pub fn separator (&mut self, separator : &str) -> &mut Self {
+    separator.clone_into (&mut self.separator);
+    self
+  }
+
+  pub fn terminator (&mut self, terminator : &str) -> &mut Self {
+    terminator.clone_into (&mut self.terminator);
+    self
+  }
+
+  pub const fn start (&mut self, start : usize) -> &mut Self {
+    self.start = start;
+    self
+  }
+
+  pub const fn pad_width (&mut self, pad_width : usize) -> &mut Self {
+    self.pad_width = pad_width;
+    self
+  }
+
+  pub const fn position (&mut self, position : CounterPosition) -> &mut Self {
+    self.position = position;
+    self
+  }
+
+  /// Largest index (inclusive) that will be tried; `None` allows every `usize`
+  pub const fn max_index (&mut self, max_index : Option <usize>) -> &mut Self {
+    self.max_index = max_index;
+    self
+  }
+
+  pub const fn lookup (&mut self, lookup : IndexLookup) -> &mut Self {
+    self.lookup = lookup;
+    self
+  }
+
+  /// Number of candidate names tried by
+  /// [`file_new_append`](#method.file_new_append) before giving up
+  pub const fn attempts (&mut self, attempts : usize) -> &mut Self {
+    self.attempts = attempts;
+    self
+  }
+
+  /// Extensions recognized by `CounterPosition::BeforeKnownExtension`
+  pub fn extensions (&mut self, extensions : &[&str]) -> &mut Self {
+    self.extensions = extensions.iter().map (ToString::to_string).collect();
+    self
+  }
+
+  pub fn build (&mut self) -> Self {
+    self.clone()
+  }
+
+  /// Returns the file path with the counter for the given index inserted into the file
+  /// name.
+  ///
+  /// No check is made whether the file exists or whether `index` is within the
+  /// configured range.
+  ///
+  /// # Errors
+  ///
+  /// - Not a file
+
+  pub fn path_for_index (&self, file_path : &path::Path, index : usize)
+    -> Result <path::PathBuf, FileError>
+  {
+    if !is_file (file_path)? {
+      return Err (FileError::NotAFile)
+    }
+    self.path_for_index_unchecked (file_path, index)
+  }
+
+  /// Returns the first non-pre-existing file path starting from index `start`.
+  ///
+  /// This function only queries for the next available filename, no directories or
+  /// files are created.
+  ///
+  /// # Errors
+  ///
+  /// - Not a file
+  /// - Index exhausted: every index up to `max_index` is taken
+  ///
+  /// ```
+  /// # use std::path::Path;
+  /// # use rs_utils::file::{FileError, IncrementalNamer};
+  /// let e = IncrementalNamer::default().start (1).max_index (Some (0)).build()
+  ///   .next_path (Path::new ("somefile")).err().unwrap();
+  /// assert!(matches!(e, FileError::IndexExhausted));
+  /// assert_eq!(e.to_string(), "incremental index exhausted");
+  /// ```
+
+  pub fn next_path (&self, file_path : &path::Path)
+    -> Result <path::PathBuf, FileError>
+  {
+    if !is_file (file_path)? {
+      return Err (FileError::NotAFile)
+    }
+    self.next_path_from (file_path, self.start).map (|(_, file_pathbuf)| file_pathbuf)
+  }
+
+  /// Returns how many indices remain from the next available one up to `max_index`,
+  /// inclusive, or `None` if there is no maximum.
+  ///
+  /// With the `ScanAfterMax` lookup every remaining index is free; with the other
+  /// lookups indices above the next available one may already be taken.
+  ///
+  /// # Errors
+  ///
+  /// - Not a file
+  /// - I/O errors from checking existence or reading the directory
+  ///
+  /// # Examples
+  ///
+  /// ```
+  /// # use rs_utils::file::IncrementalNamer;
+  /// let temp_dir  = tempfile::Builder::new().prefix ("tmp").tempdir().unwrap();
+  /// let file_path = temp_dir.path().join ("somefile");
+  /// let namer = IncrementalNamer::default().max_index (Some (2)).build();
+  /// assert_eq!(namer.indices_remaining (&file_path).unwrap(), Some (3));
+  /// namer.file_new_append (&file_path).unwrap();
+  /// assert_eq!(namer.indices_remaining (&file_path).unwrap(), Some (2));
+  /// namer.file_new_append (&file_path).unwrap();
+  /// namer.file_new_append (&file_path).unwrap();
+  /// assert_eq!(namer.indices_remaining (&file_path).unwrap(), Some (0));
+  /// ```
+
+  pub fn indices_remaining (&self, file_path : &path::Path)
+    -> Result <Option <usize>, FileError>
+  {
+    if !is_file (file_path)? {
+      return Err (FileError::NotAFile)
+    }
+    let Some (max_index) = self.max_index else {
+      return Ok (None)
+    };
+    match self.next_path_from (file_path, self.start) {
+      Ok ((index, _)) => Ok (Some ((max_index - index).saturating_add (1))),
+      Err (FileError::IndexExhausted) => Ok (Some (0)),
+      Err (e) => Err (e)
+    }
+  }
+
+  /// Returns the first non-pre-existing directory path starting from index `start`.
+  ///
+  /// Follows the same naming rules as [`next_path`](#method.next_path), applied to the
+  /// last component of `dir_path`, which may end with a separator. No directories are
+  /// created.
+  ///
+  /// # Errors
+  ///
+  /// - No file name: `dir_path` has no last component to append the counter to
+  /// - Index exhausted (&#x261e; see [`next_path`](#method.next_path))
+
+  pub fn next_dir_path (&self, dir_path : &path::Path)
+    -> Result <path::PathBuf, FileError>
+  {
+    if dir_path.file_name().is_none() {
+      return Err (FileError::NoFileName)
+    }
+    self.next_path_from (dir_path, self.start).map (|(_, dir_pathbuf)| dir_pathbuf)
+  }
+
+  /// Atomically creates the first available directory, recursively creating parent
+  /// directories, and returns the path that was created.
+  ///
+  /// The directory is created with `fs::create_dir`, so when a concurrent process
+  /// creates the same name first, the search continues from the next index, for at most
+  /// `attempts` candidate names.
+  ///
+  /// # Errors
+  ///
+  /// - No file name (&#x261e; see [`next_dir_path`](#method.next_dir_path))
+  /// - Index exhausted (&#x261e; see [`next_path`](#method.next_path))
+  /// - Attempts exhausted
+  /// - I/O errors from creating the directories
+
+  pub fn dir_new (&self, dir_path : &path::Path) -> Result <path::PathBuf, FileError> {
+    if dir_path.file_name().is_none() {
+      return Err (FileError::NoFileName)
+    }
+    ensure_parent_dir (dir_path)?;
+    self.create_from_unchecked (dir_path, self.start,
+      |dir_pathbuf| Ok (fs::create_dir (dir_pathbuf)?)
+    ).map (|(_, dir_pathbuf, ())| dir_pathbuf)
+  }
+
+  /// Atomically creates the first available file in append mode, recursively creating
+  /// parent directories, and returns the path that was created.
+  ///
+  /// When a concurrent process creates the same name first, the search continues from
+  /// the next index, for at most `attempts` candidate names.
+  ///
+  /// # Errors
+  ///
+  /// - Not a file
+  /// - Index exhausted (&#x261e; see [`next_path`](#method.next_path))
+  /// - Attempts exhausted
+
+  pub fn file_new_append (&self, file_path : &path::Path)
+    -> Result <(path::PathBuf, fs::File), FileError>
+  {
+    self.file_new_append_from (file_path, self.start)
+      .map (|(_, file_pathbuf, file)| (file_pathbuf, file))
+  }
+
+  /// Opens the first available file with the given options, returning the path that was
+  /// opened.
+  ///
+  /// With `create_new` set, as in the default
+  /// [`FileOptions`](../open/struct.FileOptions.html), the file is created atomically
+  /// and the search continues from the next index when a concurrent process creates the
+  /// same name first. Without it the first name that does not exist at lookup time is
+  /// opened.
+  ///
+  /// # Examples
+  ///
+  /// ```
+  /// # use rs_utils::file::{FileOptions, IncrementalNamer};
+  /// let temp_dir  = tempfile::Builder::new().prefix ("tmp").tempdir().unwrap();
+  /// let file_path = temp_dir.path().join ("somefile");
+  /// let mut options = FileOptions::new();
+  /// options.read (true);
+  /// #[cfg(unix)]
+  /// options.mode (0o600);
+  /// let namer = IncrementalNamer::default();
+  /// let (path, _file) = namer.file_new_with (&file_path, &options).unwrap();
+  /// assert_eq!(path, temp_dir.path().join ("somefile-0"));
+  /// let (path, _file) = namer.file_new_with (&file_path, &options).unwrap();
+  /// assert_eq!(path, temp_dir.path().join ("somefile-1"));
+  /// ```
+  ///
+  /// # Errors
+  ///
+  /// - Not a file
+  /// - Index exhausted (&#x261e; see [`next_path`](#method.next_path))
+  /// - Attempts exhausted
+  /// - I/O errors from
+  ///   [`FileOptions::open`](../open/struct.FileOptions.html#method.open)
+
+  pub fn file_new_with (&self, file_path : &path::Path, options : &FileOptions)
+    -> Result <(path::PathBuf, fs::File), FileError>
+  {
+    self.create_from (file_path, self.start, |file_pathbuf| options.open (file_pathbuf))
+      .map (|(_, file_pathbuf, file)| (file_pathbuf, file))
+  }
+
+  /// Returns the index and path of every existing member of the series, sorted by
+  /// index.
+  ///
+  /// The parent directory is read once; entries are matched by name only, following the
+  /// same rules as the [`IndexLookup`](enum.IndexLookup.html) scan modes, and may be of
+  /// any file type.
+  ///
+  /// # Errors
+  ///
+  /// - Not a file
+  /// - I/O errors from reading the directory
+  ///
+  /// # Examples
+  ///
+  /// ```
+  /// # use rs_utils::file::IncrementalNamer;
+  /// let temp_dir  = tempfile::Builder::new().prefix ("tmp").tempdir().unwrap();
+  /// let file_path = temp_dir.path().join ("somefile");
+  /// let namer     = IncrementalNamer::default();
+  /// assert!(namer.series (&file_path).unwrap().is_empty());
+  /// assert_eq!(namer.latest (&file_path).unwrap(), None);
+  /// namer.file_new_append (&file_path).unwrap();
+  /// namer.file_new_append (&file_path).unwrap();
+  /// assert_eq!(namer.series (&file_path).unwrap(), [
+  ///   (0, temp_dir.path().join ("somefile-0")),
+  ///   (1, temp_dir.path().join ("somefile-1"))
+  /// ]);
+  /// assert_eq!(namer.latest (&file_path).unwrap(),
+  ///   Some ((1, temp_dir.path().join ("somefile-1"))));
+  /// ```
+
+  pub fn series (&self, file_path : &path::Path)
+    -> Result <Vec <(usize, path::PathBuf)>, FileError>
+  {
+    if !is_file (file_path)? {
+      return Err (FileError::NotAFile)
+    }
+    let dir = file_path.parent().unwrap_or_else (|| path::Path::new (""));
+    Ok (self.scan_members (dir, &self.name_parts (file_path)?)?.into_iter().collect())
+  }
+
+  /// Returns the index and path of the existing member of the series with the highest
+  /// index
+  ///
+  /// # Errors
+  ///
+  /// &#x261e; See [`series`](#method.series)
+
+  pub fn latest (&self, file_path : &path::Path)
+    -> Result <Option <(usize, path::PathBuf)>, FileError>
+  {
+    if !is_file (file_path)? {
+      return Err (FileError::NotAFile)
+    }
+    let dir = file_path.parent().unwrap_or_else (|| path::Path::new (""));
+    Ok (self.scan_members (dir, &self.name_parts (file_path)?)?.pop_last())
+  }
+
+  /// Reopens the member of the series with the highest index in append mode, or creates
+  /// the first file of the series with [`file_new_append`](#method.file_new_append) if
+  /// there is none.
+  ///
+  /// # Errors
+  ///
+  /// - Errors from [`series`](#method.series)
+  /// - Errors from [`file_new_append`](#method.file_new_append) if the series is empty
+  /// - I/O errors from opening the latest file
+  ///
+  /// # Examples
+  ///
+  /// ```
+  /// # use std::io::Write;
+  /// # use rs_utils::file::IncrementalNamer;
+  /// let temp_dir  = tempfile::Builder::new().prefix ("tmp").tempdir().unwrap();
+  /// let file_path = temp_dir.path().join ("log");
+  /// let namer     = IncrementalNamer::default();
+  /// let (path, mut file) = namer.open_latest_append (&file_path).unwrap();
+  /// assert_eq!(path, temp_dir.path().join ("log-0"));
+  /// file.write_all (b"first run\n").unwrap();
+  /// let (path, mut file) = namer.open_latest_append (&file_path).unwrap();
+  /// assert_eq!(path, temp_dir.path().join ("log-0"));
+  /// file.write_all (b"second run\n").unwrap();
+  /// assert_eq!(std::fs::read_to_string (path).unwrap(), "first run\nsecond run\n");
+  /// ```
+
+  pub fn open_latest_append (&self, file_path : &path::Path)
+    -> Result <(path::PathBuf, fs::File), FileError>
+  {
+    self.open_latest_append_index (file_path)
+      .map (|(_, file_pathbuf, file)| (file_pathbuf, file))
+  }
+
+  /// Like `open_latest_append` but also returns the index of the opened file
+  pub(crate) fn open_latest_append_index (&self, file_path : &path::Path)
+    -> Result <(usize, path::PathBuf, fs::File), FileError>
+  {
+    match self.latest (file_path)? {
+      Some ((index, file_pathbuf)) => {
+        let file = fs::OpenOptions::new().append (true).open (&file_pathbuf)?;
+        Ok ((index, file_pathbuf, file))
+      }
+      None => self.file_new_append_from (file_path, self.start)
+    }
+  }
+
+  /// Like `file_new_append` but starts the search from index `start` and also returns
+  /// the index of the created file
+  pub(crate) fn file_new_append_from (&self, file_path : &path::Path, start : usize)
+    -> Result <(usize, path::PathBuf, fs::File), FileError>
+  {
+    self.create_from (file_path, start, file_new_append)
+  }
+
+  /// Calls `create` on the first available path with index at least `start`, moving on
+  /// to the next index whenever it fails with `AlreadyExists`, for at most `attempts`
+  /// candidate names
+  pub(crate) fn create_from <T, F> (&self,
+    file_path : &path::Path, start : usize, create : F
+  ) -> Result <(usize, path::PathBuf, T), FileError> where
+    F : FnMut (&path::Path) -> Result <T, FileError>
+  {
+    if !is_file (file_path)? {
+      return Err (FileError::NotAFile)
+    }
+    self.create_from_unchecked (file_path, start, create)
+  }
+
+  fn create_from_unchecked <T, F> (&self,
+    file_path : &path::Path, start : usize, mut create : F
+  ) -> Result <(usize, path::PathBuf, T), FileError> where
+    F : FnMut (&path::Path) -> Result <T, FileError>
+  {
+    let mut start = start;
+    for _ in 0..self.attempts {
+      let (index, file_pathbuf) = self.next_path_from (file_path, start)?;
+      match create (file_pathbuf.as_path()) {
+        Ok (created) => return Ok ((index, file_pathbuf, created)),
+        Err (e) if e.kind() == io::ErrorKind::AlreadyExists =>
+          start = index.checked_add (1).ok_or (FileError::IndexExhausted)?,
+        Err (e) => return Err (e)
+      }
+    }
+    Err (FileError::AttemptsExhausted)
+  }
+
+  /// Returns the first available path with index at least `start`, together with its
+  /// index
+  fn next_path_from (&self, file_path : &path::Path, start : usize)
+    -> Result <(usize, path::PathBuf), FileError>
+  {
+    let dir   = file_path.parent().unwrap_or_else (|| path::Path::new (""));
+    let parts = self.name_parts (file_path)?;
+    let end   = self.max_index.unwrap_or (usize::MAX);
+    let index = match self.lookup {
+      IndexLookup::Probe => {
+        let mut free = None;
+        for i in start..=end {
+          if !self.join_index (dir, &parts, i).try_exists()? {
+            free = Some (i);
+            break
+          }
+        }
+        free
+      }
+      IndexLookup::ScanAfterMax => {
+        let members = self.scan_members (dir, &parts)?;
+        match members.range (start..).next_back() {
+          Some ((max, _)) => max.checked_add (1),
+          None => Some (start)
+        }.filter (|i| *i <= end)
+      }
+      IndexLookup::ScanFirstGap => {
+        let members = self.scan_members (dir, &parts)?;
+        (start..=end).find (|i| !members.contains_key (i))
+      }
+    };
+    index.map (|i| (i, self.join_index (dir, &parts, i)))
+      .ok_or (FileError::IndexExhausted)
+  }
+
+  /// Reads the directory once and returns all entries whose name matches the naming
+  /// scheme, keyed by index.
+  ///
+  /// Only names in the exact form produced by the namer are matched, so with a pad
+  /// width of 3 `name-007` is index 7 but `name-7` and `name-0007` are ignored. A
+  /// missing directory contains no members.
+  fn scan_members (&self, dir : &path::Path, parts : &NameParts)
+    -> Result <collections::BTreeMap <usize, path::PathBuf>, FileError>
+  {
+    let (before, after) = parts;
+    let read_dir = if dir.as_os_str().is_empty() {
+      path::Path::new (".")
+    } else {
+      dir
+    };
+    let entries = match fs::read_dir (read_dir) {
+      Ok (entries) => entries,
+      Err (e) if e.kind() == io::ErrorKind::NotFound =>
+        return Ok (collections::BTreeMap::new()),
+      Err (e) => return Err (e.into())
+    };
+    let mut members = collections::BTreeMap::new();
+    for entry in entries {
+      let name = entry?.file_name();
+      if let Some (index) = self.parse_index (before, after, &name) {
+        members.insert (index, dir.join (name));
+      }
+    }
+    Ok (members)
+  }
+
+  /// Returns the index encoded in `name` if it is `before` + counter + `after`
+  fn parse_index (&self, before : &ffi::OsStr, after : &ffi::OsStr, name : &ffi::OsStr)
+    -> Option <usize>
+  {
+    let digits = name.as_encoded_bytes()
+      .strip_prefix (before.as_encoded_bytes())?
+      .strip_suffix (after.as_encoded_bytes())?;
+    if digits.is_empty() || !digits.iter().all (u8::is_ascii_digit) {
+      return None
+    }
+    let digits = str::from_utf8 (digits).ok()?;
+    let index  = digits.parse::<usize>().ok()?;
+    (self.counter (index) == digits).then_some (index)
+  }
+
+  fn counter (&self, index : usize) -> String {
+    format!("{index:0width$}", width = self.pad_width)
+  }
+
+  /// Returns the parts of the file name that go before and after the counter
+  fn name_parts (&self, file_path : &path::Path) -> Result <NameParts, FileError> {
+    let file_name = file_path.file_name().ok_or (FileError::NotAFile)?;
+    let split = match self.position {
+      CounterPosition::AfterName => None,
+      CounterPosition::BeforeFirstExtension => split_first_extension (file_name),
+      CounterPosition::BeforeLastExtension  => split_last_extension (file_name),
+      CounterPosition::BeforeKnownExtension =>
+        split_known_extension (file_name, &self.extensions)
+          .or_else (|| split_last_extension (file_name)),
+      CounterPosition::Prefix => {
+        let mut after = ffi::OsString::from (&self.separator);
+        after.push (file_name);
+        return Ok ((ffi::OsString::new(), after))
+      }
+    };
+    let (mut before, after) = match split {
+      Some ((stem, extension)) => {
+        let mut after = ffi::OsString::from (&self.terminator);
+        after.push (".");
+        after.push (extension);
+        (stem.to_os_string(), after)
+      }
+      None => (file_name.to_os_string(), ffi::OsString::from (&self.terminator))
+    };
+    before.push (&self.separator);
+    Ok ((before, after))
+  }
+
+  fn join_index (&self, dir : &path::Path, parts : &NameParts, index : usize)
+    -> path::PathBuf
+  {
+    join_counter (dir, parts, &self.counter (index))
+  }
+
+  /// Returns the file path with an arbitrary string in place of the counter
+  #[cfg(feature="timestamp")]
+  pub(crate) fn path_for_counter (&self, file_path : &path::Path, counter : &str)
+    -> Result <path::PathBuf, FileError>
+  {
+    if !is_file (file_path)? {
+      return Err (FileError::NotAFile)
+    }
+    let dir = file_path.parent().unwrap_or_else (|| path::Path::new (""));
+    Ok (join_counter (dir, &self.name_parts (file_path)?, counter))
+  }
+
+  fn path_for_index_unchecked (&self, file_path : &path::Path, index : usize)
+    -> Result <path::PathBuf, FileError>
+  {
+    let dir = file_path.parent().unwrap_or_else (|| path::Path::new (""));
+    Ok (self.join_index (dir, &self.name_parts (file_path)?, index))
+  }
+}
+
+impl Default for IncrementalNamer {
+  fn default() -> Self {
+    IncrementalNamer {
+      separator:  "-".to_string(),
+      terminator: String::new(),
+      start:      0,
+      pad_width:  0,
+      position:   CounterPosition::AfterName,
+      max_index:  Some (INCREMENTAL_MAX_INDEX),
+      lookup:     IndexLookup::Probe,
+      attempts:   INCREMENTAL_CREATE_ATTEMPTS,
+      extensions: COMPOUND_EXTENSIONS.iter().map (ToString::to_string).collect()
+    }
+  }
+}
+
+fn join_counter (dir : &path::Path, parts : &NameParts, counter : &str)
+  -> path::PathBuf
+{
+  let (before, after) = parts;
+  let mut name = before.clone();
+  name.push (counter);
+  name.push (after);
+  dir.join (name)
+}
+
+/// Splits at the first dot that is not part of a leading run of dots
+fn split_first_extension (file_name : &ffi::OsStr)
+  -> Option <(&ffi::OsStr, &ffi::OsStr)>
+{
+  let bytes   = file_name.as_encoded_bytes();
+  let leading = bytes.iter().take_while (|b| **b == b'.').count();
+  bytes[leading..].iter().position (|b| *b == b'.')
+    .map (|i| split_at_dot (file_name, leading + i))
+}
+
+/// Splits at the last dot following the same rules as `Path::extension`
+fn split_last_extension (file_name : &ffi::OsStr)
+  -> Option <(&ffi::OsStr, &ffi::OsStr)>
+{
+  let bytes = file_name.as_encoded_bytes();
+  if bytes == b".." {
+    return None
+  }
+  match bytes.iter().rposition (|b| *b == b'.') {
+    None | Some (0) => None,
+    Some (i) => Some (split_at_dot (file_name, i))
+  }
+}
+
+/// Splits before the longest of the given extensions that ends the file name, leaving
+/// a non-empty name that is not only dots
+fn split_known_extension <'a> (file_name : &'a ffi::OsStr, extensions : &[String])
+  -> Option <(&'a ffi::OsStr, &'a ffi::OsStr)>
+{
+  let bytes   = file_name.as_encoded_bytes();
+  let leading = bytes.iter().take_while (|b| **b == b'.').count();
+  extensions.iter().filter_map (|extension| {
+    let extension = extension.trim_start_matches ('.').as_bytes();
+    let i = bytes.len().checked_sub (extension.len() + 1)?;
+    (!extension.is_empty() && i > leading && bytes[i] == b'.' &&
+      bytes[i+1..].eq_ignore_ascii_case (extension)).then_some (i)
+  }).min().map (|i| split_at_dot (file_name, i))
+}
+
+/// Splits into the parts before and after the dot at byte index `i`
+fn split_at_dot (file_name : &ffi::OsStr, i : usize) -> (&ffi::OsStr, &ffi::OsStr) {
+  let bytes = file_name.as_encoded_bytes();
+  debug_assert_eq!(bytes[i], b'.');
+  // SAFETY: both parts are split immediately before or after an ASCII dot, so they are
+  // valid encoded OS strings
+  unsafe {
+    (ffi::OsStr::from_encoded_bytes_unchecked (&bytes[..i]),
+      ffi::OsStr::from_encoded_bytes_unchecked (&bytes[i+1..]))
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn counter_positions() {
+    let file_path = path::Path::new ("dir/backup.tar.gz");
+    let mut namer = IncrementalNamer::default();
+    for (position, expected) in [
+      (CounterPosition::AfterName,            "dir/backup.tar.gz-3"),
+      (CounterPosition::BeforeFirstExtension, "dir/backup-3.tar.gz"),
+      (CounterPosition::BeforeLastExtension,  "dir/backup.tar-3.gz"),
+      (CounterPosition::BeforeKnownExtension, "dir/backup-3.tar.gz"),
+      (CounterPosition::Prefix,               "dir/3-backup.tar.gz")
+    ] {
+      assert_eq!(namer.position (position).path_for_index (file_path, 3).unwrap(),
+        path::Path::new (expected));
+    }
+  }
+
+  #[test]
+  fn no_extension_falls_back_to_after_name() {
+    let mut namer = IncrementalNamer::default();
+    for position in [
+      CounterPosition::BeforeFirstExtension,
+      CounterPosition::BeforeLastExtension,
+      CounterPosition::BeforeKnownExtension
+    ] {
+      namer.position (position);
+      assert_eq!(namer.path_for_index (path::Path::new (".env"), 0).unwrap(),
+        path::Path::new (".env-0"));
+      assert_eq!(namer.path_for_index (path::Path::new ("file"), 0).unwrap(),
+        path::Path::new ("file-0"));
+    }
+  }
+
+  #[test]
+  fn known_extensions() {
+    let mut namer = IncrementalNamer::default()
+      .position (CounterPosition::BeforeKnownExtension).build();
+    let path_for = |namer : &IncrementalNamer, file_name : &str|
+      namer.path_for_index (path::Path::new (file_name), 0).unwrap();
+    for (file_name, expected) in [
+      ("backup.tar.gz",     "backup-0.tar.gz"),
+      ("backup.TAR.ZST",    "backup-0.TAR.ZST"),
+      ("app.2026.log.gz",   "app.2026-0.log.gz"),
+      ("notes.v1.txt",      "notes.v1-0.txt"),
+      ("log.gz",            "log-0.gz"),
+      (".tar.gz",           ".tar-0.gz"),
+      (".cache.tar.gz",     ".cache-0.tar.gz"),
+      (".env.local",        ".env-0.local")
+    ] {
+      assert_eq!(path_for (&namer, file_name), path::Path::new (expected));
+    }
+    namer.extensions (&["v1.txt"]);
+    assert_eq!(path_for (&namer, "notes.v1.txt"), path::Path::new ("notes-0.v1.txt"));
+    assert_eq!(path_for (&namer, "backup.tar.gz"), path::Path::new ("backup.tar-0.gz"));
+  }
+
+  #[test]
+  fn separator_and_padding() {
+    let namer = IncrementalNamer::default().separator (".").pad_width (3)
+      .position (CounterPosition::BeforeLastExtension).build();
+    assert_eq!(namer.path_for_index (path::Path::new ("capture.pcap"), 1).unwrap(),
+      path::Path::new ("capture.001.pcap"));
+    assert_eq!(namer.path_for_index (path::Path::new ("capture.pcap"), 1234).unwrap(),
+      path::Path::new ("capture.1234.pcap"));
+  }
+
+  #[test]
+  fn scan_lookup() {
+    let temp_dir  = tempfile::Builder::new().prefix ("tmp").tempdir().unwrap();
+    let file_path = temp_dir.path().join ("run.log");
+    for name in [
+      "run_000.log", "run_001.log", "run_003.log", "run_0004.log", "run_5.log",
+      "run_abc.log", "run_006.txt", "other_007.log", "run_.log"
+    ] {
+      fs::File::create (temp_dir.path().join (name)).unwrap();
+    }
+    let mut namer = IncrementalNamer::default();
+    namer.separator ("_").pad_width (3).position (CounterPosition::BeforeLastExtension);
+    assert_eq!(namer.lookup (IndexLookup::ScanAfterMax).next_path (&file_path).unwrap(),
+      temp_dir.path().join ("run_004.log"));
+    assert_eq!(namer.lookup (IndexLookup::ScanFirstGap).next_path (&file_path).unwrap(),
+      temp_dir.path().join ("run_002.log"));
+    assert_eq!(namer.lookup (IndexLookup::Probe).next_path (&file_path).unwrap(),
+      temp_dir.path().join ("run_002.log"));
+    assert_eq!(namer.lookup (IndexLookup::ScanAfterMax).start (4)
+      .next_path (&file_path).unwrap(), temp_dir.path().join ("run_004.log"));
+    assert_eq!(namer.lookup (IndexLookup::ScanFirstGap).start (0).max_index (Some (1))
+      .next_path (&file_path).unwrap_err().to_string(), "incremental index exhausted");
+    assert_eq!(namer.max_index (None).lookup (IndexLookup::ScanAfterMax)
+      .file_new_append (&temp_dir.path().join ("new/run.log")).unwrap().0,
+      temp_dir.path().join ("new/run_000.log"));
+  }
+
+  #[cfg(unix)]
+  #[test]
+  fn non_unicode_names() {
+    use std::os::unix::ffi::OsStrExt;
+    let temp_dir  = tempfile::Builder::new().prefix ("tmp").tempdir().unwrap();
+    let file_path = temp_dir.path().join (ffi::OsStr::from_bytes (b"\xff.\xfe.\xfd"));
+    let mut namer = IncrementalNamer::default();
+    namer.separator ("\u{e9}").position (CounterPosition::BeforeFirstExtension);
+    assert_eq!(namer.path_for_index (&file_path, 2).unwrap(),
+      temp_dir.path().join (ffi::OsStr::from_bytes (b"\xff\xc3\xa92.\xfe.\xfd")));
+    namer.position (CounterPosition::BeforeLastExtension);
+    assert_eq!(namer.path_for_index (&file_path, 2).unwrap(),
+      temp_dir.path().join (ffi::OsStr::from_bytes (b"\xff.\xfe\xc3\xa92.\xfd")));
+    namer.position (CounterPosition::Prefix);
+    assert_eq!(namer.path_for_index (&file_path, 2).unwrap(),
+      temp_dir.path().join (ffi::OsStr::from_bytes (b"2\xc3\xa9\xff.\xfe.\xfd")));
+    namer.position (CounterPosition::AfterName).lookup (IndexLookup::ScanAfterMax);
+    for i in [0, 4] {
+      fs::File::create (namer.path_for_index (&file_path, i).unwrap()).unwrap();
+    }
+    fs::File::create (temp_dir.path().join (ffi::OsStr::from_bytes (b"\xff\xc3\xa9")))
+      .unwrap();
+    assert_eq!(namer.next_path (&file_path).unwrap(),
+      namer.path_for_index (&file_path, 5).unwrap());
+  }
+
+  #[test]
+  fn directories() {
+    let temp_dir = tempfile::Builder::new().prefix ("tmp").tempdir().unwrap();
+    let dir_path = temp_dir.path().join ("runs/run/");
+    let namer    = IncrementalNamer::default().separator ("_").pad_width (2).build();
+    assert_eq!(namer.next_dir_path (&dir_path).unwrap(),
+      temp_dir.path().join ("runs/run_00"));
+    assert_eq!(namer.dir_new (&dir_path).unwrap(),
+      temp_dir.path().join ("runs/run_00"));
+    fs::File::create (temp_dir.path().join ("runs/run_01")).unwrap();
+    assert_eq!(namer.dir_new (&dir_path).unwrap(),
+      temp_dir.path().join ("runs/run_02"));
+    assert!(temp_dir.path().join ("runs/run_02").is_dir());
+    assert_eq!(namer.series (&temp_dir.path().join ("runs/run")).unwrap().len(), 3);
+    assert!(matches!(namer.dir_new (path::Path::new ("runs/..")),
+      Err (FileError::NoFileName)));
+  }
+
+  #[test]
+  fn start_and_max_index() {
+    let temp_dir  = tempfile::Builder::new().prefix ("tmp").tempdir().unwrap();
+    let file_path = temp_dir.path().join ("somefile");
+    let namer = IncrementalNamer::default().start (5).max_index (Some (6)).build();
+    assert_eq!(namer.file_new_append (&file_path).unwrap().0,
+      temp_dir.path().join ("somefile-5"));
+    assert_eq!(namer.file_new_append (&file_path).unwrap().0,
+      temp_dir.path().join ("somefile-6"));
+    assert_eq!(namer.file_new_append (&file_path).unwrap_err().to_string(),
+      "incremental index exhausted");
+    assert_eq!(namer.indices_remaining (&file_path).unwrap(), Some (0));
+    assert_eq!(IncrementalNamer::default().max_index (None).build()
+      .indices_remaining (&file_path).unwrap(), None);
+    assert_eq!(IncrementalNamer::default().indices_remaining (&file_path).unwrap(),
+      Some (INCREMENTAL_MAX_INDEX + 1));
+  }
+
+  #[test]
+  fn probe_errors_propagate() {
+    let temp_dir  = tempfile::Builder::new().prefix ("tmp").tempdir().unwrap();
+    let not_a_dir = temp_dir.path().join ("somefile");
+    fs::write (&not_a_dir, "").unwrap();
+    let e = IncrementalNamer::default().next_path (&not_a_dir.join ("log"))
+      .unwrap_err();
+    assert_eq!(e.kind(), io::ErrorKind::NotADirectory);
+  }
+}
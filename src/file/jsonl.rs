@@ -0,0 +1,314 @@
+//! JSON Lines writing and streaming reading
+
+use std::{fs, io, marker, path};
+
+use super::FileError;
+
+/// When a [`JsonlWriter`](struct.JsonlWriter.html) passes buffered lines to its sink
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub enum JsonlFlush {
+  /// Write and flush each line as soon as it is serialized
+  #[default]
+  Line,
+  /// Buffer lines and write and flush them together once this many are pending
+  Lines (u64),
+  /// Buffer lines until [`flush`](struct.JsonlWriter.html#method.flush) is called or
+  /// the writer is dropped
+  Manual
+}
+
+/// What a [`JsonlReader`](struct.JsonlReader.html) does with a line that can not be
+/// parsed
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub enum JsonlBadLine {
+  /// Return the error and end the iteration
+  #[default]
+  Stop,
+  /// Count the line as skipped and continue with the next one
+  Skip
+}
+
+/// Writes values as JSON Lines, one compact JSON value per line, to any `io::Write`
+/// sink.
+///
+/// Lines are passed to the sink with a single `write_all` per flush, so sinks that
+/// treat each write as a record, such as
+/// [`RotatingFileWriter`](../rotate/struct.RotatingFileWriter.html),
+/// [`LockedAppendFile`](../lock/struct.LockedAppendFile.html) or
+/// [`DurableFile`](../durable/struct.DurableFile.html), never split a line. Pending
+/// lines are written when the writer is dropped, ignoring errors; call
+/// [`flush`](#method.flush) first to observe them.
+///
+/// # Examples
+///
+/// ```
+/// # use rs_utils::file::{file_new_append_incremental, JsonlReader, JsonlWriter};
+/// let temp_dir  = tempfile::Builder::new().prefix ("tmp").tempdir().unwrap();
+/// let file_path = temp_dir.path().join ("events.jsonl");
+/// let (path, file) = file_new_append_incremental (&file_path).unwrap();
+/// let mut writer = JsonlWriter::new (file);
+/// writer.write (&("start", 1)).unwrap();
+/// writer.write (&("stop", 2)).unwrap();
+/// drop (writer);
+/// assert_eq!(std::fs::read_to_string (&path).unwrap(),
+///   "[\"start\",1]\n[\"stop\",2]\n");
+/// let events = JsonlReader::<(String, u32), _>::open (&path).unwrap()
+///   .collect::<Result <Vec <_>, _>>().unwrap();
+/// assert_eq!(events, [("start".to_string(), 1), ("stop".to_string(), 2)]);
+/// ```
+
+pub struct JsonlWriter <T : ?Sized, W : io::Write> {
+  sink    : W,
+  flush   : JsonlFlush,
+  pending : Vec <u8>,
+  lines   : u64,
+  _value  : marker::PhantomData <fn (&T)>
+}
+
+/// Reads values from JSON Lines, skipping blank lines.
+///
+/// Parse errors are reported as
+/// [`FileError::JsonLine`](../enum.FileError.html#variant.JsonLine) with the 1-based
+/// line number.
+///
+/// # Examples
+///
+/// ```
+/// # use rs_utils::file::{FileError, JsonlBadLine, JsonlReader};
+/// let input = "1\n2\nnot json\n\n4\n";
+/// let mut reader = JsonlReader::<u32, _>::new (input.as_bytes());
+/// let values = reader.by_ref().collect::<Vec <_>>();
+/// assert_eq!(values[..2].iter().map (|v| *v.as_ref().unwrap()).collect::<Vec <_>>(),
+///   [1, 2]);
+/// assert!(matches!(values[2], Err (FileError::JsonLine { line: 3, .. })));
+/// assert_eq!(values.len(), 3);
+///
+/// let mut reader = JsonlReader::<u32, _>::new (input.as_bytes());
+/// reader.bad_line (JsonlBadLine::Skip);
+/// let values = reader.by_ref().collect::<Result <Vec <_>, _>>().unwrap();
+/// assert_eq!(values, [1, 2, 4]);
+/// assert_eq!(reader.skipped(), 1);
+/// ```
+
+pub struct JsonlReader <T, R : io::BufRead> {
+  reader   : R,
+  bad_line : JsonlBadLine,
+  line     : u64,
+  skipped  : u64,
+  done     : bool,
+  buf      : Vec <u8>,
+  _value   : marker::PhantomData <fn() -> T>
+}
+
+impl <T, W> JsonlWriter <T, W> where
+  T : serde::Serialize + ?Sized,
+  W : io::Write
+{
+  /// Writes each line as soon as it is serialized
+  pub const fn new (sink : W) -> Self {
+    JsonlWriter {
+      sink,
+      flush:   JsonlFlush::Line,
+      pending: Vec::new(),
+      lines:   0,
+      _value:  marker::PhantomData
+    }
+  }
+
+  pub const fn flush_policy (&mut self, flush : JsonlFlush) -> &mut Self {
+    self.flush = flush;
+    self
+  }
+
+  pub const fn get_ref (&self) -> &W {
+    &self.sink
+  }
+
+  pub const fn get_mut (&mut self) -> &mut W {
+    &mut self.sink
+  }
+
+  /// Serializes the value as one line and writes it according to the flush policy.
+  ///
+  /// # Errors
+  ///
+  /// - Serialize: the value could not be serialized; nothing is written
+  /// - I/O errors from the sink
+
+  pub fn write (&mut self, value : &T) -> Result <(), FileError> {
+    let start = self.pending.len();
+    if let Err (e) = serde_json::to_writer (&mut self.pending, value) {
+      self.pending.truncate (start);
+      return Err (FileError::Serialize (e.into()))
+    }
+    self.pending.push (b'\n');
+    self.lines += 1;
+    let due = match self.flush {
+      JsonlFlush::Line      => true,
+      JsonlFlush::Lines (n) => self.lines >= n,
+      JsonlFlush::Manual    => false
+    };
+    if due {
+      self.flush()?;
+    }
+    Ok (())
+  }
+
+  /// Writes all pending lines to the sink and flushes it.
+  ///
+  /// # Errors
+  ///
+  /// I/O errors from the sink; the pending lines are kept
+
+  pub fn flush (&mut self) -> Result <(), FileError> {
+    if !self.pending.is_empty() {
+      self.sink.write_all (&self.pending)?;
+      self.pending.clear();
+      self.lines = 0;
+    }
+    self.sink.flush()?;
+    Ok (())
+  }
+}
+
+impl <T : ?Sized, W : io::Write> Drop for JsonlWriter <T, W> {
+  fn drop (&mut self) {
+    if !self.pending.is_empty() && self.sink.write_all (&self.pending).is_ok() {
+      let _ = self.sink.flush();
+    }
+  }
+}
+
+impl <T> JsonlReader <T, io::BufReader <fs::File>> where
+  T : serde::de::DeserializeOwned
+{
+  /// Opens a JSON Lines file for reading.
+  ///
+  /// # Errors
+  ///
+  /// I/O errors from opening the file
+
+  pub fn open (file_path : &path::Path) -> Result <Self, FileError> {
+    Ok (Self::new (io::BufReader::new (fs::File::open (file_path)?)))
+  }
+}
+
+impl <T, R> JsonlReader <T, R> where
+  T : serde::de::DeserializeOwned,
+  R : io::BufRead
+{
+  pub const fn new (reader : R) -> Self {
+    JsonlReader {
+      reader,
+      bad_line: JsonlBadLine::Stop,
+      line:     0,
+      skipped:  0,
+      done:     false,
+      buf:      Vec::new(),
+      _value:   marker::PhantomData
+    }
+  }
+
+  pub const fn bad_line (&mut self, bad_line : JsonlBadLine) -> &mut Self {
+    self.bad_line = bad_line;
+    self
+  }
+
+  /// Number of the last line read, starting from 1
+  pub const fn line (&self) -> u64 {
+    self.line
+  }
+
+  /// Number of lines skipped because they could not be parsed
+  pub const fn skipped (&self) -> u64 {
+    self.skipped
+  }
+}
+
+impl <T, R> Iterator for JsonlReader <T, R> where
+  T : serde::de::DeserializeOwned,
+  R : io::BufRead
+{
+  type Item = Result <T, FileError>;
+
+  fn next (&mut self) -> Option <Self::Item> {
+    while !self.done {
+      self.buf.clear();
+      match self.reader.read_until (b'\n', &mut self.buf) {
+        Ok (0) => self.done = true,
+        Ok (_) => {}
+        Err (e) => return Some (Err (e.into()))
+      }
+      if self.done {
+        break
+      }
+      self.line += 1;
+      let line = self.buf.trim_ascii();
+      if line.is_empty() {
+        continue
+      }
+      match serde_json::from_slice (line) {
+        Ok (value) => return Some (Ok (value)),
+        Err (_) if self.bad_line == JsonlBadLine::Skip => self.skipped += 1,
+        Err (error) => {
+          self.done = true;
+          let line = self.line;
+          return Some (Err (FileError::JsonLine { line, error: error.into() }))
+        }
+      }
+    }
+    None
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use std::io::Write;
+  use crate::file::{IncrementalNamer, RotatingFileWriter, RotationPolicy};
+  use super::*;
+
+  #[test]
+  fn flush_policies() {
+    let mut writer = JsonlWriter::<u32, _>::new (Vec::new());
+    writer.flush_policy (JsonlFlush::Lines (2));
+    writer.write (&1).unwrap();
+    assert!(writer.get_ref().is_empty());
+    writer.write (&2).unwrap();
+    assert_eq!(writer.get_ref(), b"1\n2\n");
+    writer.flush_policy (JsonlFlush::Manual).write (&3).unwrap();
+    assert_eq!(writer.get_ref(), b"1\n2\n");
+    writer.flush().unwrap();
+    assert_eq!(writer.get_ref(), b"1\n2\n3\n");
+  }
+
+  #[test]
+  fn serialize_error() {
+    let mut writer = JsonlWriter::new (Vec::new());
+    let value = std::collections::BTreeMap::from ([((1, 2), 3)]);
+    let e = writer.write (&value).unwrap_err();
+    assert!(matches!(e, FileError::Serialize (_)));
+    assert_eq!(e.kind(), io::ErrorKind::InvalidData);
+    writer.flush().unwrap();
+    assert!(writer.get_ref().is_empty());
+  }
+
+  #[test]
+  fn rotating_sink() {
+    let temp_dir  = tempfile::Builder::new().prefix ("tmp").tempdir().unwrap();
+    let file_path = temp_dir.path().join ("events.jsonl");
+    let sink = RotatingFileWriter::new (&file_path, IncrementalNamer::default(),
+      RotationPolicy::default().max_records (Some (1)).build()).unwrap();
+    let mut writer = JsonlWriter::new (sink);
+    writer.flush_policy (JsonlFlush::Lines (2));
+    for value in 0..4 {
+      writer.write (&serde_json::json!({ "value": value })).unwrap();
+    }
+    writer.get_mut().flush().unwrap();
+    let read = |name : &str| JsonlReader::<serde_json::Value, _>::open (
+      &temp_dir.path().join (name)
+    ).unwrap().map (|value| value.unwrap()["value"].as_u64().unwrap())
+      .collect::<Vec <_>>();
+    assert_eq!(read ("events.jsonl-0"), [0, 1]);
+    assert_eq!(read ("events.jsonl-1"), [2, 3]);
+  }
+}
@@ -0,0 +1,198 @@
+//! Lexical path manipulation and confinement to a base directory
+
+use std::{fs, path};
+
+use super::{file_new_append, is_file, FileError};
+
+/// Collapses `.` and `..` components and repeated separators without accessing the
+/// filesystem.
+///
+/// A `..` removes the preceding normal component; leading `..` components of a relative
+/// path are kept and `..` directly after the root is dropped. An empty result is `.`.
+/// Since symbolic links are not resolved, `link/..` is collapsed even if `link` points
+/// to a directory elsewhere. A trailing separator is not preserved.
+///
+/// # Examples
+///
+/// ```
+/// # use std::path::Path;
+/// # use rs_utils::file::normalize_lexically;
+/// assert_eq!(normalize_lexically (Path::new ("a/./b/../c//d")), Path::new ("a/c/d"));
+/// assert_eq!(normalize_lexically (Path::new ("../a/../../b")), Path::new ("../../b"));
+/// assert_eq!(normalize_lexically (Path::new ("/../a")), Path::new ("/a"));
+/// assert_eq!(normalize_lexically (Path::new ("a/..")), Path::new ("."));
+/// ```
+
+pub fn normalize_lexically (path : &path::Path) -> path::PathBuf {
+  use path::Component;
+  let mut components : Vec <Component> = Vec::new();
+  for component in path.components() {
+    match component {
+      Component::CurDir => {}
+      Component::ParentDir => match components.last() {
+        Some (Component::Normal (_)) => { components.pop(); }
+        Some (Component::RootDir | Component::Prefix (_)) => {}
+        _ => components.push (component)
+      }
+      _ => components.push (component)
+    }
+  }
+  if components.is_empty() {
+    return path::PathBuf::from (".")
+  }
+  components.iter().collect()
+}
+
+/// Returns a relative path that leads from the directory `from` to `to`, computed
+/// lexically after normalizing both.
+///
+/// Returns `None` if only one of the paths is absolute, if they have different
+/// prefixes, or if `from` has more leading `..` components than `to`, since the names
+/// of the directories above would be needed.
+///
+/// # Examples
+///
+/// ```
+/// # use std::path::Path;
+/// # use rs_utils::file::relative_path;
+/// assert_eq!(
+///   relative_path (Path::new ("/data/runs/1"), Path::new ("/data/logs/a.log")),
+///   Some (Path::new ("../../logs/a.log").to_path_buf()));
+/// assert_eq!(relative_path (Path::new ("a"), Path::new ("a")),
+///   Some (Path::new (".").to_path_buf()));
+/// assert_eq!(relative_path (Path::new ("/a"), Path::new ("b")), None);
+/// assert_eq!(relative_path (Path::new ("../a"), Path::new ("b")), None);
+/// ```
+
+pub fn relative_path (from : &path::Path, to : &path::Path) -> Option <path::PathBuf> {
+  use path::Component;
+  let from = normalize_lexically (from);
+  let to   = normalize_lexically (to);
+  if from.has_root() != to.has_root() {
+    return None
+  }
+  let mut from_components = from.components().filter (|c| *c != Component::CurDir)
+    .peekable();
+  let mut to_components = to.components().filter (|c| *c != Component::CurDir)
+    .peekable();
+  while let (Some (a), Some (b)) = (from_components.peek(), to_components.peek()) {
+    if a != b {
+      break
+    }
+    from_components.next();
+    to_components.next();
+  }
+  let mut relative = path::PathBuf::new();
+  for component in from_components {
+    match component {
+      Component::Normal (_) => relative.push (".."),
+      _ => return None
+    }
+  }
+  for component in to_components {
+    match component {
+      Component::Normal (_) | Component::ParentDir => relative.push (component),
+      _ => return None
+    }
+  }
+  if relative.as_os_str().is_empty() {
+    relative.push (".");
+  }
+  Some (relative)
+}
+
+/// Joins an untrusted relative path onto `base`, rejecting paths that could lead
+/// outside of it.
+///
+/// The untrusted path is normalized lexically; it is rejected if it is absolute (or has
+/// a Windows prefix) or if it starts with `..` after normalization. Symbolic links
+/// inside `base` are not resolved, so a link can still lead outside of it.
+///
+/// # Examples
+///
+/// ```
+/// # use std::path::Path;
+/// # use rs_utils::file::{safe_join, FileError};
+/// let base = Path::new ("/srv/output");
+/// assert_eq!(safe_join (base, Path::new ("run/./a/../log.txt")).unwrap(),
+///   Path::new ("/srv/output/run/log.txt"));
+/// assert!(matches!(safe_join (base, Path::new ("run/../../etc/passwd")),
+///   Err (FileError::PathEscapesBase)));
+/// assert!(matches!(safe_join (base, Path::new ("/etc/passwd")),
+///   Err (FileError::PathEscapesBase)));
+/// ```
+///
+/// # Errors
+///
+/// - Path escapes base
+
+pub fn safe_join (base : &path::Path, untrusted : &path::Path)
+  -> Result <path::PathBuf, FileError>
+{
+  use path::Component;
+  if untrusted.has_root() ||
+    untrusted.components().any (|c| matches!(c, Component::Prefix (_)))
+  {
+    return Err (FileError::PathEscapesBase)
+  }
+  let relative = normalize_lexically (untrusted);
+  match relative.components().next() {
+    Some (Component::ParentDir) => Err (FileError::PathEscapesBase),
+    Some (Component::CurDir)    => Ok (base.to_path_buf()),
+    _ => Ok (base.join (relative))
+  }
+}
+
+/// Creates a new file in append mode at an untrusted relative path confined to `root`,
+/// recursively creating parent directories, and returns the path that was created.
+///
+/// The trailing separator of `relative` is checked before normalization, so a path
+/// naming a directory is still rejected as not a file.
+///
+/// # Examples
+///
+/// ```
+/// # use rs_utils::file::{file_new_append_within, FileError};
+/// let temp_dir = tempfile::Builder::new().prefix ("tmp").tempdir().unwrap();
+/// let root     = temp_dir.path();
+/// let (path, _file) =
+///   file_new_append_within (root, "jobs/7/out.log".as_ref()).unwrap();
+/// assert_eq!(path, root.join ("jobs/7/out.log"));
+/// assert!(matches!(file_new_append_within (root, "../out.log".as_ref()),
+///   Err (FileError::PathEscapesBase)));
+/// ```
+///
+/// # Errors
+///
+/// - Not a file
+/// - Path escapes base (&#x261e; see [`safe_join`](fn.safe_join.html))
+/// - Errors from [`file_new_append`](../fn.file_new_append.html)
+
+pub fn file_new_append_within (root : &path::Path, relative : &path::Path)
+  -> Result <(path::PathBuf, fs::File), FileError>
+{
+  if !is_file (relative)? {
+    return Err (FileError::NotAFile)
+  }
+  let file_path = safe_join (root, relative)?;
+  let file = file_new_append (&file_path)?;
+  Ok ((file_path, file))
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn relative_path_round_trip() {
+    for (from, to) in [
+      ("a/b/c", "a/d"), ("a", "a/b/c"), ("/", "/x/y"), ("/x/y", "/"),
+      ("../a", "../b/c"), ("a/../b", "./c/../d"), (".", "..")
+    ] {
+      let (from, to) = (path::Path::new (from), path::Path::new (to));
+      let relative = relative_path (from, to).unwrap();
+      assert_eq!(normalize_lexically (&from.join (&relative)), normalize_lexically (to),
+        "{from:?} {to:?} {relative:?}");
+    }
+  }
+}
@@ -0,0 +1,303 @@
+//! Advisory locking for files shared between processes
+
+use std::{fmt, fs, io, path, thread, time};
+
+use super::{file_new_append, write_fmt_record, FileError, FileOptions};
+
+/// How an advisory lock is acquired
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub enum LockMode {
+  /// Wait until the lock is available
+  #[default]
+  Blocking,
+  /// Fail with `io::ErrorKind::WouldBlock` if the lock is held elsewhere
+  NonBlocking,
+  /// Retry until the lock is available, failing with `io::ErrorKind::TimedOut` after
+  /// the given duration
+  Timeout (time::Duration)
+}
+
+/// How long a [`LockedAppendFile`](struct.LockedAppendFile.html) holds its lock
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub enum LockScope {
+  /// Lock around each write, so that other processes can write in between
+  #[default]
+  Write,
+  /// Lock once when the file is opened and keep the lock until it is dropped
+  File
+}
+
+/// Acquires an exclusive advisory lock (`flock` on Unix, `LockFileEx` on Windows) on
+/// the file.
+///
+/// The lock is released by `fs::File::unlock` or when every handle to the open file
+/// description is closed. Advisory locks only exclude other processes that also lock
+/// the file.
+///
+/// # Errors
+///
+/// - `io::ErrorKind::WouldBlock` if the lock is held elsewhere in non-blocking mode
+/// - `io::ErrorKind::TimedOut` if the lock could not be acquired in time
+/// - I/O errors from locking
+
+pub fn lock_file (file : &fs::File, mode : LockMode) -> Result <(), FileError> {
+  const MAX_BACKOFF : time::Duration = time::Duration::from_millis (50);
+  match mode {
+    LockMode::Blocking    => file.lock()?,
+    LockMode::NonBlocking => file.try_lock().map_err (io::Error::from)?,
+    LockMode::Timeout (timeout) => {
+      let start = time::Instant::now();
+      let mut backoff = time::Duration::from_millis (1);
+      loop {
+        match file.try_lock() {
+          Ok (()) => break,
+          Err (fs::TryLockError::WouldBlock) => {}
+          Err (fs::TryLockError::Error (e)) => return Err (e.into())
+        }
+        let elapsed = start.elapsed();
+        if elapsed >= timeout {
+          return Err (io::Error::from (io::ErrorKind::TimedOut).into())
+        }
+        thread::sleep (backoff.min (timeout - elapsed));
+        backoff = (backoff * 2).min (MAX_BACKOFF);
+      }
+    }
+  }
+  Ok (())
+}
+
+/// A file in append mode that is shared between processes, with an exclusive advisory
+/// lock held around each write or for the lifetime of the file.
+///
+/// Each call to `write` is treated as one record and is written in full with
+/// `write_all` while the lock is held, so records written through `LockedAppendFile` by
+/// different processes never interleave; a `write!` or `writeln!` call is written under
+/// a single lock as well.
+///
+/// # Examples
+///
+/// ```
+/// # use std::io::Write;
+/// # use rs_utils::file::{LockedAppendFile, LockMode, LockScope};
+/// let temp_dir  = tempfile::Builder::new().prefix ("tmp").tempdir().unwrap();
+/// let file_path = temp_dir.path().join ("shared.log");
+/// let mut file  = LockedAppendFile::open (
+///   &file_path, LockMode::Blocking, LockScope::Write
+/// ).unwrap();
+/// file.write_all (b"record\n").unwrap();
+/// assert_eq!(std::fs::read_to_string (&file_path).unwrap(), "record\n");
+/// ```
+
+#[derive(Debug)]
+pub struct LockedAppendFile {
+  file  : fs::File,
+  mode  : LockMode,
+  scope : LockScope
+}
+
+impl LockedAppendFile {
+  /// Wraps a file that was opened in append mode, acquiring the lock immediately for
+  /// `LockScope::File`.
+  ///
+  /// # Errors
+  ///
+  /// &#x261e; See [`lock_file`](fn.lock_file.html)
+
+  pub fn new (file : fs::File, mode : LockMode, scope : LockScope)
+    -> Result <Self, FileError>
+  {
+    if scope == LockScope::File {
+      lock_file (&file, mode)?;
+    }
+    Ok (LockedAppendFile { file, mode, scope })
+  }
+
+  /// Opens the file in append mode, creating it and its parent directories if missing.
+  ///
+  /// # Errors
+  ///
+  /// - Errors from [`FileOptions::open`](../open/struct.FileOptions.html#method.open)
+  /// - Errors from [`lock_file`](fn.lock_file.html) for `LockScope::File`
+
+  pub fn open (file_path : &path::Path, mode : LockMode, scope : LockScope)
+    -> Result <Self, FileError>
+  {
+    Self::new (FileOptions::open_or_create_append().open (file_path)?, mode, scope)
+  }
+
+  /// Creates a new file in append mode as by
+  /// [`file_new_append`](../fn.file_new_append.html).
+  ///
+  /// # Errors
+  ///
+  /// - Errors from [`file_new_append`](../fn.file_new_append.html)
+  /// - Errors from [`lock_file`](fn.lock_file.html) for `LockScope::File`
+
+  pub fn create_new (file_path : &path::Path, mode : LockMode, scope : LockScope)
+    -> Result <Self, FileError>
+  {
+    Self::new (file_new_append (file_path)?, mode, scope)
+  }
+
+  pub const fn file (&self) -> &fs::File {
+    &self.file
+  }
+
+  pub const fn mode (&self) -> LockMode {
+    self.mode
+  }
+
+  pub const fn scope (&self) -> LockScope {
+    self.scope
+  }
+
+  /// Releases the lock and returns the file
+  pub fn into_inner (self) -> fs::File {
+    if self.scope == LockScope::File {
+      let _ = self.file.unlock();
+    }
+    self.file
+  }
+}
+
+impl io::Write for LockedAppendFile {
+  fn write (&mut self, buf : &[u8]) -> io::Result <usize> {
+    if self.scope == LockScope::File {
+      self.file.write_all (buf)?;
+      return Ok (buf.len())
+    }
+    lock_file (&self.file, self.mode)?;
+    let result = self.file.write_all (buf);
+    self.file.unlock()?;
+    result.map (|()| buf.len())
+  }
+
+  fn write_fmt (&mut self, args : fmt::Arguments) -> io::Result <()> {
+    write_fmt_record (self, args)
+  }
+
+  fn flush (&mut self) -> io::Result <()> {
+    self.file.flush()
+  }
+}
+
+#[cfg(all(test, target_os = "linux"))]
+mod tests {
+  use std::{env, process};
+  use std::io::Write;
+  use super::*;
+
+  const CHILD_ENV : &str = "RS_UTILS_LOCK_TEST_CHILD";
+  const RECORDS   : usize = 32;
+  const RECORD    : usize = 64 * 1024;
+
+  /// Runs `child` in a child process executing this test binary
+  fn spawn_child (test : &str, file_path : &path::Path, arg : &str) -> process::Child {
+    process::Command::new (env::current_exe().unwrap())
+      .args (["--exact", test, "--nocapture", "--test-threads=1"])
+      .env (CHILD_ENV, format!("{}\n{arg}", file_path.display()))
+      .stdout (process::Stdio::null())
+      .spawn().unwrap()
+  }
+
+  fn child_args() -> Option <(path::PathBuf, String)> {
+    let var = env::var (CHILD_ENV).ok()?;
+    let (file_path, arg) = var.split_once ('\n').unwrap();
+    Some ((file_path.into(), arg.to_string()))
+  }
+
+  /// Child: appends records of a single repeated byte with per-write locking, half of
+  /// them formatted from two parts
+  #[test]
+  fn child_writer() {
+    let Some ((file_path, arg)) = child_args() else { return };
+    let mut file = LockedAppendFile::open (
+      &file_path, LockMode::Blocking, LockScope::Write
+    ).unwrap();
+    let record = arg[..1].repeat (RECORD);
+    let (first, second) = record.split_at (RECORD / 2);
+    for i in 0..RECORDS {
+      if i % 2 == 0 {
+        file.write_all (record.as_bytes()).unwrap();
+      } else {
+        write!(file, "{first}{second}").unwrap();
+      }
+    }
+  }
+
+  /// Child: exits with the error kind of acquiring the lock in the given mode
+  #[test]
+  fn child_locker() {
+    let Some ((file_path, arg)) = child_args() else { return };
+    let mode = match arg.as_str() {
+      "nonblocking" => LockMode::NonBlocking,
+      _             => LockMode::Timeout (time::Duration::from_millis (100))
+    };
+    let code = match LockedAppendFile::open (&file_path, mode, LockScope::File) {
+      Ok (_) => 0,
+      Err (e) if e.kind() == io::ErrorKind::WouldBlock => 2,
+      Err (e) if e.kind() == io::ErrorKind::TimedOut   => 3,
+      Err (_) => 1
+    };
+    process::exit (code)
+  }
+
+  #[test]
+  fn records_not_torn() {
+    if child_args().is_some() {
+      return
+    }
+    let temp_dir  = tempfile::Builder::new().prefix ("tmp").tempdir().unwrap();
+    let file_path = temp_dir.path().join ("shared");
+    let children  = ["a", "b", "c", "d"].map (|arg|
+      spawn_child ("file::lock::tests::child_writer", &file_path, arg));
+    for mut child in children {
+      assert!(child.wait().unwrap().success());
+    }
+    let contents = fs::read (&file_path).unwrap();
+    assert_eq!(contents.len(), 4 * RECORDS * RECORD);
+    for record in contents.chunks (RECORD) {
+      assert!(record.iter().all (|byte| *byte == record[0]));
+    }
+  }
+
+  #[test]
+  fn formatted_record_written_once() {
+    /// Appends to the file through another handle while it is being formatted
+    struct Intruder (path::PathBuf);
+    impl fmt::Display for Intruder {
+      fn fmt (&self, f : &mut fmt::Formatter) -> fmt::Result {
+        let mut other = fs::OpenOptions::new().append (true).open (&self.0).unwrap();
+        other.write_all (b"x").unwrap();
+        f.write_str ("b")
+      }
+    }
+    if child_args().is_some() {
+      return
+    }
+    let temp_dir  = tempfile::Builder::new().prefix ("tmp").tempdir().unwrap();
+    let file_path = temp_dir.path().join ("shared");
+    let mut file  = LockedAppendFile::create_new (
+      &file_path, LockMode::Blocking, LockScope::Write
+    ).unwrap();
+    write!(file, "a{}c", Intruder (file_path.clone())).unwrap();
+    assert_eq!(fs::read_to_string (&file_path).unwrap(), "xabc");
+  }
+
+  #[test]
+  fn acquisition_modes() {
+    if child_args().is_some() {
+      return
+    }
+    let temp_dir  = tempfile::Builder::new().prefix ("tmp").tempdir().unwrap();
+    let file_path = temp_dir.path().join ("shared");
+    let status = |arg| spawn_child ("file::lock::tests::child_locker", &file_path, arg)
+      .wait().unwrap().code();
+    let file = LockedAppendFile::open (&file_path, LockMode::Blocking, LockScope::File)
+      .unwrap();
+    assert_eq!(status ("nonblocking"), Some (2));
+    assert_eq!(status ("timeout"), Some (3));
+    drop (file.into_inner());
+    assert_eq!(status ("nonblocking"), Some (0));
+  }
+}
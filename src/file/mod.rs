@@ -0,0 +1,634 @@
+//! File utilities
+#![expect(clippy::module_name_repetitions)]
+
+use std::{error, fmt, fs, io, path};
+
+pub mod atomic;
+pub mod classify;
+pub mod durable;
+pub mod follow;
+pub mod incremental;
+#[cfg(feature="jsonl")]
+#[cfg_attr(docsrs, doc(cfg(feature="jsonl")))]
+pub mod jsonl;
+pub mod lexical;
+pub mod lock;
+pub mod name;
+pub mod open;
+pub mod pidlock;
+pub mod record;
+pub mod retention;
+pub mod rotate;
+#[cfg(feature="run-dir")]
+#[cfg_attr(docsrs, doc(cfg(feature="run-dir")))]
+pub mod run;
+pub mod tee;
+#[cfg(feature="timestamp")]
+#[cfg_attr(docsrs, doc(cfg(feature="timestamp")))]
+pub mod timestamp;
+pub use self::atomic::*;
+pub use self::classify::*;
+pub use self::durable::*;
+pub use self::follow::*;
+pub use self::incremental::*;
+#[cfg(feature="jsonl")]
+#[cfg_attr(docsrs, doc(cfg(feature="jsonl")))]
+pub use self::jsonl::*;
+pub use self::lexical::*;
+pub use self::lock::*;
+pub use self::name::*;
+pub use self::open::*;
+pub use self::pidlock::*;
+pub use self::record::*;
+pub use self::retention::*;
+pub use self::rotate::*;
+#[cfg(feature="run-dir")]
+#[cfg_attr(docsrs, doc(cfg(feature="run-dir")))]
+pub use self::run::*;
+pub use self::tee::*;
+#[cfg(feature="timestamp")]
+#[cfg_attr(docsrs, doc(cfg(feature="timestamp")))]
+pub use self::timestamp::*;
+
+/// Errors returned by file utilities.
+///
+/// Converts from and into `io::Error`, so `?` works in functions returning either
+/// type; errors other than `Io` become an `io::Error` of the corresponding
+/// [`kind`](#method.kind) with the `FileError` as payload. New variants may be added
+/// in minor releases, so matches need a wildcard arm.
+///
+/// ```
+/// # use std::{io, path::Path};
+/// # use rs_utils::file::{file_new_append, FileError};
+/// fn open() -> Result <std::fs::File, io::Error> {
+///   Ok (file_new_append (Path::new ("somepath/"))?)
+/// }
+/// let e = open().err().unwrap();
+/// assert_eq!(e.kind(), io::ErrorKind::InvalidInput);
+/// assert!(matches!(e.into_inner().unwrap().downcast::<FileError>().as_deref(),
+///   Ok (FileError::NotAFile)));
+/// ```
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum FileError {
+  /// The path can not be opened as a file (&#x261e; see [`is_file`](fn.is_file.html))
+  NotAFile,
+  /// The path has no last component to name
+  NoFileName,
+  /// The file name fails a validation rule (&#x261e; see
+  /// [`validate_file_name`](fn.validate_file_name.html))
+  InvalidFileName (FileNameRule),
+  /// An existing ancestor of the path is not a directory (&#x261e; see
+  /// [`ensure_parent_dir`](fn.ensure_parent_dir.html))
+  ParentNotADirectory (path::PathBuf),
+  /// Something already exists at the path (&#x261e; see
+  /// [`ensure_file_absent`](fn.ensure_file_absent.html))
+  Exists (PathKind),
+  /// An untrusted path is absolute or leads outside of its base directory (&#x261e; see
+  /// [`safe_join`](fn.safe_join.html))
+  PathEscapesBase,
+  /// A timestamp pattern is not understood, formats to an empty string or contains a
+  /// path separator (&#x261e; see
+  /// [`file_path_timestamped`](timestamp/fn.file_path_timestamped.html))
+  InvalidTimestampPattern,
+  /// A record is longer than [`RECORD_MAX_LEN`](record/constant.RECORD_MAX_LEN.html)
+  RecordTooLong { len : usize },
+  /// A line of a JSON Lines file could not be parsed (&#x261e; see
+  /// [`JsonlReader`](jsonl/struct.JsonlReader.html))
+  JsonLine { line : u64, error : Box <dyn error::Error + Send + Sync> },
+  /// A value could not be serialized (&#x261e; see
+  /// [`JsonlWriter`](jsonl/struct.JsonlWriter.html) and
+  /// [`RunDir`](run/struct.RunDir.html))
+  Serialize (Box <dyn error::Error + Send + Sync>),
+  /// A lock file is held by the live process with the given id (&#x261e; see
+  /// [`PidLock`](pidlock/struct.PidLock.html))
+  Locked { pid : u32 },
+  /// Every index allowed by the naming scheme is taken
+  IndexExhausted,
+  /// Every attempt to atomically create an incrementally named file collided with an
+  /// existing file
+  AttemptsExhausted,
+  /// Underlying I/O error
+  Io (io::Error)
+}
+
+/// Default number of candidate names tried by
+/// [`file_new_append_incremental`](fn.file_new_append_incremental.html) before
+/// giving up
+pub const INCREMENTAL_CREATE_ATTEMPTS : usize = 64;
+
+/// Largest index tried by the incremental functions and the default
+/// [`IncrementalNamer`](incremental/struct.IncrementalNamer.html); once every index up
+/// to it is taken they fail with [`FileError::IndexExhausted`](enum.FileError.html)
+pub const INCREMENTAL_MAX_INDEX : usize = 999_999;
+
+/// Calls `file_new_append` on the path returned by feeding the file path to
+/// `file_path_incremental`, retrying with the next index if another process created
+/// the file first.
+///
+/// Returns the path of the file that was actually created.
+///
+/// Equivalent to `file_new_append_incremental_attempts` with
+/// [`INCREMENTAL_CREATE_ATTEMPTS`](constant.INCREMENTAL_CREATE_ATTEMPTS.html).
+///
+/// # Errors
+///
+/// - Not a file (&#x261e; see [`file_path_incremental`](fn.file_path_incremental.html))
+/// - Attempts exhausted (&#x261e; see [`file_new_append_incremental_attempts`](
+///   fn.file_new_append_incremental_attempts.html))
+
+pub fn file_new_append_incremental (file_path : &path::Path)
+  -> Result <(path::PathBuf, fs::File), FileError>
+{
+  file_new_append_incremental_attempts (file_path, INCREMENTAL_CREATE_ATTEMPTS)
+}
+
+/// Atomically creates the first available incrementally named file, trying at most
+/// `attempts` candidate names.
+///
+/// The file is opened with `create_new`, so when a concurrent process creates the same
+/// name between the availability check and the open, the `AlreadyExists` error is
+/// caught and the search continues from the next index.
+///
+/// # Errors
+///
+/// - Not a file (&#x261e; see [`file_path_incremental`](fn.file_path_incremental.html))
+/// - Attempts exhausted:
+///
+/// ```
+/// # use std::io::ErrorKind; use std::path::Path;
+/// # use rs_utils::file::{file_new_append_incremental_attempts, FileError};
+/// let e = file_new_append_incremental_attempts (Path::new ("somefile"), 0)
+///   .err().unwrap();
+/// assert!(matches!(e, FileError::AttemptsExhausted));
+/// assert_eq!(e.kind(), ErrorKind::AlreadyExists);
+/// assert_eq!(e.to_string(), "incremental file attempts exhausted");
+/// ```
+
+pub fn file_new_append_incremental_attempts (file_path : &path::Path, attempts : usize)
+  -> Result <(path::PathBuf, fs::File), FileError>
+{
+  IncrementalNamer::default().attempts (attempts).file_new_append (file_path)
+}
+
+/// Returns the index and path of every existing file in the series generated by
+/// `file_path_incremental`, sorted by index.
+///
+/// Equivalent to [`IncrementalNamer::series`](
+/// incremental/struct.IncrementalNamer.html#method.series) on the default namer.
+///
+/// # Errors
+///
+/// - Not a file (&#x261e; see [`file_path_incremental`](fn.file_path_incremental.html))
+/// - I/O errors from reading the parent directory
+
+pub fn file_series_incremental (file_path : &path::Path)
+  -> Result <Vec <(usize, path::PathBuf)>, FileError>
+{
+  IncrementalNamer::default().series (file_path)
+}
+
+/// Returns the highest index of an existing file in the series generated by
+/// `file_path_incremental`.
+///
+/// # Examples
+///
+/// ```
+/// # use rs_utils::file::{file_index_latest_incremental, file_new_append_incremental};
+/// let temp_dir  = tempfile::Builder::new().prefix ("tmp").tempdir().unwrap();
+/// let file_path = temp_dir.path().join ("somefile");
+/// assert_eq!(file_index_latest_incremental (&file_path).unwrap(), None);
+/// file_new_append_incremental (&file_path).unwrap();
+/// file_new_append_incremental (&file_path).unwrap();
+/// assert_eq!(file_index_latest_incremental (&file_path).unwrap(), Some (1));
+/// ```
+///
+/// # Errors
+///
+/// &#x261e; See [`file_series_incremental`](fn.file_series_incremental.html)
+
+pub fn file_index_latest_incremental (file_path : &path::Path)
+  -> Result <Option <usize>, FileError>
+{
+  Ok (IncrementalNamer::default().latest (file_path)?.map (|(index, _)| index))
+}
+
+/// Reopens the latest file in the series generated by `file_path_incremental` in
+/// append mode, or creates `-0` if there is none.
+///
+/// Equivalent to [`IncrementalNamer::open_latest_append`](
+/// incremental/struct.IncrementalNamer.html#method.open_latest_append) on the default
+/// namer.
+///
+/// # Errors
+///
+/// - Not a file (&#x261e; see [`file_path_incremental`](fn.file_path_incremental.html))
+/// - I/O errors from reading the parent directory or opening the file
+
+pub fn file_open_latest_append_incremental (file_path : &path::Path)
+  -> Result <(path::PathBuf, fs::File), FileError>
+{
+  IncrementalNamer::default().open_latest_append (file_path)
+}
+
+/// Returns the directory path with suffix `-N` where `N` gives the first available
+/// non-pre-existing name starting from `0`.
+///
+/// This is the directory counterpart of `file_path_incremental`; the path may end with
+/// a separator. No directories are created.
+///
+/// # Examples
+///
+/// ```
+/// # use std::path::Path;
+/// # use rs_utils::file::dir_path_incremental;
+/// assert_eq!(
+///   dir_path_incremental (Path::new ("somedir/runs/run/")).unwrap(),
+///   Path::new ("somedir/runs/run-0")
+/// );
+/// ```
+///
+/// # Errors
+///
+/// - No file name (&#x261e; see [`IncrementalNamer::next_dir_path`](
+///   incremental/struct.IncrementalNamer.html#method.next_dir_path))
+
+pub fn dir_path_incremental (dir_path : &path::Path)
+  -> Result <path::PathBuf, FileError>
+{
+  IncrementalNamer::default().next_dir_path (dir_path)
+}
+
+/// Atomically creates the next incrementally named directory, recursively creating
+/// parent directories, and returns its path.
+///
+/// Equivalent to [`IncrementalNamer::dir_new`](
+/// incremental/struct.IncrementalNamer.html#method.dir_new) on the default namer.
+///
+/// # Examples
+///
+/// ```
+/// # use rs_utils::file::dir_new_incremental;
+/// let temp_dir = tempfile::Builder::new().prefix ("tmp").tempdir().unwrap();
+/// let dir_path = temp_dir.path().join ("runs/run");
+/// let dir_0    = dir_new_incremental (&dir_path).unwrap();
+/// let dir_1    = dir_new_incremental (&dir_path).unwrap();
+/// assert_eq!(dir_0, temp_dir.path().join ("runs/run-0"));
+/// assert_eq!(dir_1, temp_dir.path().join ("runs/run-1"));
+/// assert!(dir_1.is_dir());
+/// ```
+///
+/// # Errors
+///
+/// &#x261e; See [`IncrementalNamer::dir_new`](
+/// incremental/struct.IncrementalNamer.html#method.dir_new)
+
+pub fn dir_new_incremental (dir_path : &path::Path)
+  -> Result <path::PathBuf, FileError>
+{
+  IncrementalNamer::default().dir_new (dir_path)
+}
+
+/// Opens a new file at specified path for writing in append mode, recursively creating
+/// parent directories.
+///
+/// # Errors
+///
+/// - Not a file:
+///
+/// ```
+/// # use std::io::ErrorKind; use std::path::Path;
+/// # use rs_utils::file::{file_new_append, FileError};
+/// let e = file_new_append (Path::new ("somepath/")).err().unwrap();
+/// assert!(matches!(e, FileError::NotAFile));
+/// assert_eq!(e.kind(), ErrorKind::InvalidInput);
+/// assert_eq!(e.to_string(), "not a file");
+/// ```
+///
+/// - File already exists:
+///
+/// ```
+/// use tempfile;
+/// # use std::io::ErrorKind; use std::path::Path;
+/// # use rs_utils::file::file_new_append;
+/// # fn main () {
+///
+/// let temp_dir = tempfile::Builder::new().prefix ("tmp").tempdir().unwrap();
+/// let file_path = temp_dir.path().join (Path::new ("somefile"));
+/// let file_path = file_path.as_path();
+/// assert! (!file_path.exists());
+/// file_new_append (file_path).unwrap();
+/// let e = file_new_append (file_path).err().unwrap();
+/// assert_eq!(e.kind(), ErrorKind::AlreadyExists);
+/// assert_eq!(e.to_string(), "File exists (os error 17)");
+/// # }
+/// ```
+///
+/// - Parent is not a directory:
+///
+/// ```
+/// # use std::io::ErrorKind;
+/// # use rs_utils::file::{file_new_append, FileError};
+/// let temp_dir  = tempfile::Builder::new().prefix ("tmp").tempdir().unwrap();
+/// let file_path = temp_dir.path().join ("somefile");
+/// file_new_append (&file_path).unwrap();
+/// let e = file_new_append (&file_path.join ("nested/file")).unwrap_err();
+/// assert!(matches!(&e, FileError::ParentNotADirectory (path) if *path == file_path));
+/// assert_eq!(e.kind(), ErrorKind::NotADirectory);
+/// ```
+
+pub fn file_new_append (file_path : &path::Path) -> Result <fs::File, FileError> {
+  FileOptions::new().open (file_path)
+}
+
+/// Returns the file path appended with suffix `-N` where `N` gives the first available
+/// non-pre-existing filename starting from `0`.
+///
+/// This function only queries for the next available filename, no directories or files
+/// are created.
+///
+/// Equivalent to the default
+/// [`IncrementalNamer`](incremental/struct.IncrementalNamer.html).
+///
+/// # Examples
+///
+/// ```
+/// # use std::path::Path;
+/// # use rs_utils::file::file_path_incremental;
+/// let file_path = Path::new ("somedir/somefile");
+/// assert_eq!(
+///   file_path_incremental (file_path).unwrap().to_str().unwrap(),
+///   "somedir/somefile-0"
+/// );
+/// ```
+///
+/// # Errors
+///
+/// - Not a file:
+///
+/// ```
+/// # use std::io::ErrorKind; use std::path::Path;
+/// # use rs_utils::file::{file_path_incremental, FileError};
+/// let e = file_path_incremental (Path::new ("somepath/")).err().unwrap();
+/// assert!(matches!(e, FileError::NotAFile));
+/// assert_eq!(e.kind(), ErrorKind::InvalidInput);
+/// assert_eq!(e.to_string(), "not a file");
+/// ```
+///
+/// - Index exhausted: every index up to
+///   [`INCREMENTAL_MAX_INDEX`](constant.INCREMENTAL_MAX_INDEX.html) is taken
+/// - I/O errors from checking whether a candidate path exists, for example when a
+///   parent is not a directory:
+///
+/// ```
+/// # use rs_utils::file::file_path_incremental;
+/// let temp_dir  = tempfile::Builder::new().prefix ("tmp").tempdir().unwrap();
+/// let not_a_dir = temp_dir.path().join ("somefile");
+/// std::fs::write (&not_a_dir, "").unwrap();
+/// assert!(file_path_incremental (&not_a_dir.join ("log")).is_err());
+/// ```
+
+pub fn file_path_incremental (file_path : &path::Path)
+  -> Result <path::PathBuf, FileError>
+{
+  IncrementalNamer::default().next_path (file_path)
+}
+
+/// Like file path incremental but preserves the file extension if one is present.
+///
+/// Compound extensions such as `tar.gz` from
+/// [`COMPOUND_EXTENSIONS`](incremental/constant.COMPOUND_EXTENSIONS.html) are kept
+/// whole, giving `backup-0.tar.gz`. Equivalent to an
+/// [`IncrementalNamer`](incremental/struct.IncrementalNamer.html) with position
+/// `BeforeKnownExtension`.
+///
+/// # Examples
+///
+/// ```
+/// # use std::path::Path;
+/// # use rs_utils::file::file_path_incremental_with_extension;
+/// let file_path = Path::new ("somedir/somefile.txt");
+/// assert_eq!(
+///   file_path_incremental_with_extension (file_path).unwrap().to_str().unwrap(),
+///   "somedir/somefile-0.txt"
+/// );
+/// let file_path = Path::new ("somedir/backup.tar.gz");
+/// assert_eq!(
+///   file_path_incremental_with_extension (file_path).unwrap().to_str().unwrap(),
+///   "somedir/backup-0.tar.gz"
+/// );
+/// ```
+pub fn file_path_incremental_with_extension (file_path : &path::Path)
+  -> Result <path::PathBuf, FileError>
+{
+  IncrementalNamer::default().position (CounterPosition::BeforeKnownExtension)
+    .next_path (file_path)
+}
+
+/// If this returns true then `std::fs::File::create` will not fail with "is a
+/// directory" error.
+///
+/// This is *not* the same as `std::path::Path::is_file` which also tests whether the
+/// file actually exists.
+///
+/// # Examples
+///
+/// ```
+/// # use std::path::Path; use rs_utils::file::is_file;
+/// assert!(is_file (Path::new ("path/to/file")).unwrap());
+/// assert!(!is_file (Path::new ("path/to/directory/")).unwrap());
+/// assert!(!is_file (Path::new ("..")).unwrap());
+/// ```
+///
+/// Paths are inspected as OS strings, so any file name the OS accepts is valid,
+/// including names that are not valid unicode:
+///
+/// ```
+/// # use std::path::Path; use std::ffi::OsStr;
+/// # use rs_utils::file::is_file;
+/// use std::os::unix::ffi::OsStrExt;
+/// let garbage = [192u8, 192u8, 192u8, 192u8];
+/// let garbage_path = Path::new (OsStr::from_bytes (&garbage));
+/// assert!(is_file (garbage_path).unwrap());
+/// ```
+///
+/// # Errors
+///
+/// This function currently always succeeds.
+
+pub fn is_file (file_path : &path::Path) -> Result <bool, FileError> {
+  let bytes = file_path.as_os_str().as_encoded_bytes();
+  let trailing_separator = bytes.last()
+    .is_some_and (|b| b.is_ascii() && path::is_separator (char::from (*b)));
+  if trailing_separator {
+    return Ok (false)
+  }
+  if file_path.file_name().is_none() {
+    return Ok (false)
+  }
+  Ok (true)
+}
+
+/// `io::Write::write_fmt` for writers that treat each `write` as one record: formats
+/// into a buffer first and writes it with a single `write_all`, so that a `write!` or
+/// `writeln!` call becomes one record instead of one per formatted fragment
+pub(crate) fn write_fmt_record <W> (writer : &mut W, args : fmt::Arguments)
+  -> io::Result <()>
+where
+  W : io::Write + ?Sized
+{
+  match args.as_str() {
+    Some (s) => writer.write_all (s.as_bytes()),
+    None => writer.write_all (fmt::format (args).as_bytes())
+  }
+}
+
+impl FileError {
+  /// Returns the corresponding I/O error kind
+  pub fn kind (&self) -> io::ErrorKind {
+    match self {
+      FileError::NotAFile | FileError::NoFileName | FileError::InvalidFileName (_) |
+      FileError::PathEscapesBase | FileError::InvalidTimestampPattern |
+      FileError::RecordTooLong { .. } => io::ErrorKind::InvalidInput,
+      FileError::ParentNotADirectory (_) => io::ErrorKind::NotADirectory,
+      FileError::IndexExhausted | FileError::AttemptsExhausted |
+      FileError::Exists (_) => io::ErrorKind::AlreadyExists,
+      FileError::JsonLine { .. } | FileError::Serialize (_) =>
+        io::ErrorKind::InvalidData,
+      FileError::Locked { .. } => io::ErrorKind::WouldBlock,
+      FileError::Io (e) => e.kind()
+    }
+  }
+}
+
+impl fmt::Display for FileError {
+  fn fmt (&self, f : &mut fmt::Formatter) -> fmt::Result {
+    match self {
+      FileError::NotAFile          => write!(f, "not a file"),
+      FileError::NoFileName        => write!(f, "no file name"),
+      FileError::InvalidFileName (rule) => write!(f, "invalid file name: {rule}"),
+      FileError::ParentNotADirectory (path) =>
+        write!(f, "parent is not a directory: {}", path.display()),
+      FileError::Exists (kind)     => write!(f, "path exists: {kind}"),
+      FileError::PathEscapesBase   => write!(f, "path escapes base directory"),
+      FileError::InvalidTimestampPattern => write!(f, "invalid timestamp pattern"),
+      FileError::RecordTooLong { len } => write!(f, "record too long: {len} bytes"),
+      FileError::JsonLine { line, error } => write!(f, "line {line}: {error}"),
+      FileError::Serialize (e)     => write!(f, "serialization failed: {e}"),
+      FileError::Locked { pid }    => write!(f, "locked by process {pid}"),
+      FileError::IndexExhausted    => write!(f, "incremental index exhausted"),
+      FileError::AttemptsExhausted => write!(f, "incremental file attempts exhausted"),
+      FileError::Io (e)            => e.fmt (f)
+    }
+  }
+}
+
+impl error::Error for FileError {
+  fn source (&self) -> Option <&(dyn error::Error + 'static)> {
+    match self {
+      FileError::Io (e) => e.source(),
+      FileError::JsonLine { error, .. } | FileError::Serialize (error) =>
+        Some (error.as_ref()),
+      _ => None
+    }
+  }
+}
+
+impl From <io::Error> for FileError {
+  fn from (e : io::Error) -> Self {
+    FileError::Io (e)
+  }
+}
+
+impl From <FileError> for io::Error {
+  fn from (e : FileError) -> Self {
+    match e {
+      FileError::Io (e) => e,
+      e => io::Error::new (e.kind(), e)
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use tempfile;
+  use quickcheck;
+  use super::*;
+
+  // test that is_file() implies file creation will not give an "is a directory" error:
+  // as of Rust 1.16 (2017-01-23) this error is simply indicated by an ErrorKind::Other
+  // (other os error)
+  #[ignore] // to run test use `cargo test -- --ignored`
+  #[quickcheck_macros::quickcheck]
+  fn prop_is_file_implies_not_directory (file_path : String) -> quickcheck::TestResult {
+    let file_path = path::Path::new (file_path.as_str());
+    if !is_file (file_path).unwrap() {
+      return quickcheck::TestResult::discard()
+    }
+    if let Some (s) = file_path.parent() && !s.to_str().unwrap().is_empty() {
+      return quickcheck::TestResult::discard()
+    }
+    let temp_dir  = tempfile::Builder::new().prefix ("tmp").tempdir().unwrap();
+    let file_path = temp_dir.path().join (file_path);
+    quickcheck::TestResult::from_bool (
+      if let Err(e) = fs::OpenOptions::new().append (true).create (true)
+        .open (file_path)
+      {
+        e.kind() != io::ErrorKind::Other
+      } else {
+        true
+      }
+    )
+  }
+
+  #[cfg(unix)]
+  #[test]
+  fn non_unicode_file_names() {
+    use std::{ffi::OsStr, os::unix::ffi::OsStrExt};
+    let temp_dir = tempfile::Builder::new().prefix ("tmp").tempdir().unwrap();
+    let dir      = temp_dir.path().join (OsStr::from_bytes (b"dir\xff"));
+    let name     = dir.join (OsStr::from_bytes (b"\xc0\xc0name"));
+    let (path, _) = file_new_append_incremental (&name).unwrap();
+    assert_eq!(path, dir.join (OsStr::from_bytes (b"\xc0\xc0name-0")));
+    assert!(path.is_file());
+    let (path, _) = file_new_append_incremental (&name).unwrap();
+    assert_eq!(path, dir.join (OsStr::from_bytes (b"\xc0\xc0name-1")));
+    let name = dir.join (OsStr::from_bytes (b"\xfe.\xff"));
+    assert_eq!(file_path_incremental_with_extension (&name).unwrap(),
+      dir.join (OsStr::from_bytes (b"\xfe-0.\xff")));
+    assert!(!is_file (&dir.join (OsStr::from_bytes (b"\xff/"))).unwrap());
+  }
+
+  #[test]
+  fn file_new_append_incremental_concurrent() {
+    use std::{collections::HashSet, sync, thread};
+    let temp_dir  = tempfile::Builder::new().prefix ("tmp").tempdir().unwrap();
+    let file_path = temp_dir.path().join ("somefile");
+    let barrier   = sync::Arc::new (sync::Barrier::new (16));
+    let mut handles = Vec::new();
+    for _ in 0..16 {
+      let file_path = file_path.clone();
+      let barrier   = barrier.clone();
+      handles.push (thread::spawn (move || {
+        barrier.wait();
+        file_new_append_incremental (file_path.as_path()).unwrap().0
+      }));
+    }
+    let paths = handles.into_iter().map (|handle| handle.join().unwrap())
+      .collect::<HashSet <_>>();
+    assert_eq!(paths.len(), 16);
+    for i in 0..16 {
+      assert!(paths.contains (&temp_dir.path().join (format!("somefile-{i}"))));
+    }
+  }
+
+  #[test]
+  fn file_new_append_incremental_skips_taken_index() {
+    let temp_dir  = tempfile::Builder::new().prefix ("tmp").tempdir().unwrap();
+    let file_path = temp_dir.path().join ("somefile");
+    let (path, _) = file_new_append_incremental_attempts (&file_path, 1).unwrap();
+    assert_eq!(path, temp_dir.path().join ("somefile-0"));
+    let (path, _) = file_new_append_incremental_attempts (&file_path, 1).unwrap();
+    assert_eq!(path, temp_dir.path().join ("somefile-1"));
+  }
+}
@@ -0,0 +1,295 @@
+//! Portable file name validation and sanitization
+#![expect(clippy::module_name_repetitions)]
+
+use std::{ffi, fmt};
+
+use super::FileError;
+
+/// Maximum length of a file name in bytes accepted at every strictness level
+pub const FILE_NAME_MAX_BYTES : usize = 255;
+
+/// Characters that Windows does not allow in file names, besides control characters
+const WINDOWS_RESERVED_CHARS : &[char] =
+  &['<', '>', ':', '"', '/', '\\', '|', '?', '*'];
+
+/// Device names that Windows reserves regardless of extension
+const WINDOWS_RESERVED_NAMES : &[&str] = &[
+  "CON", "PRN", "AUX", "NUL",
+  "COM0", "COM1", "COM2", "COM3", "COM4", "COM5", "COM6", "COM7", "COM8", "COM9",
+  "LPT0", "LPT1", "LPT2", "LPT3", "LPT4", "LPT5", "LPT6", "LPT7", "LPT8", "LPT9"
+];
+
+/// Which file names [`validate_file_name`](fn.validate_file_name.html) accepts
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub enum NameStrictness {
+  /// Names that POSIX systems accept: no `/` or NUL, not `.` or `..`, and at most
+  /// [`FILE_NAME_MAX_BYTES`](constant.FILE_NAME_MAX_BYTES.html) bytes
+  Posix,
+  /// Names that can be created on both POSIX systems and Windows: additionally valid
+  /// unicode, without control characters or `<>:"\|?*`, not ending with a dot or space,
+  /// and not a reserved device name such as `CON` or `com1.txt`
+  #[default]
+  CrossPlatform,
+  /// Cross-platform names that only use the POSIX portable filename character set
+  /// `A-Z a-z 0-9 . _ -` and do not start with a hyphen
+  Portable
+}
+
+/// The rule that a file name fails (&#x261e; see
+/// [`NameStrictness`](enum.NameStrictness.html))
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum FileNameRule {
+  Empty,
+  /// The name is `.` or `..`
+  DotName,
+  /// The name contains a NUL byte
+  Nul,
+  /// The name contains a `/`
+  Separator,
+  /// The name is longer than
+  /// [`FILE_NAME_MAX_BYTES`](constant.FILE_NAME_MAX_BYTES.html)
+  TooLong,
+  /// The name is not valid unicode
+  InvalidUnicode,
+  ControlCharacter (char),
+  /// A character reserved on Windows
+  ReservedCharacter (char),
+  /// The name ends with a dot or a space
+  TrailingDotOrSpace,
+  /// A device name reserved on Windows, with or without extension
+  ReservedName,
+  /// A character outside the POSIX portable filename character set
+  NonPortableCharacter (char),
+  LeadingHyphen
+}
+
+/// Checks that `file_name` is a single file name that is valid at the given strictness.
+///
+/// Unlike [`is_file`](../fn.is_file.html), which only checks the shape of a path, this
+/// checks the name itself against the rules of the file systems and tools the name has
+/// to work with. Rules are checked in the order listed by
+/// [`FileNameRule`](enum.FileNameRule.html) and the first failing rule is reported.
+///
+/// # Examples
+///
+/// ```
+/// # use std::ffi::OsStr;
+/// # use rs_utils::file::{validate_file_name, FileError, FileNameRule, NameStrictness};
+/// let validate = |name : &str, strictness|
+///   validate_file_name (OsStr::new (name), strictness);
+/// assert!(validate ("report: final?.txt", NameStrictness::Posix).is_ok());
+/// assert!(matches!(validate ("report: final?.txt", NameStrictness::CrossPlatform),
+///   Err (FileError::InvalidFileName (FileNameRule::ReservedCharacter (':')))));
+/// assert!(matches!(validate ("con.log", NameStrictness::CrossPlatform),
+///   Err (FileError::InvalidFileName (FileNameRule::ReservedName))));
+/// assert!(matches!(validate ("résumé.txt", NameStrictness::Portable),
+///   Err (FileError::InvalidFileName (FileNameRule::NonPortableCharacter ('é')))));
+/// let e = validate ("notes.", NameStrictness::CrossPlatform).unwrap_err();
+/// assert_eq!(e.to_string(), "invalid file name: trailing dot or space");
+/// ```
+///
+/// # Errors
+///
+/// - Invalid file name with the failing rule
+
+pub fn validate_file_name (file_name : &ffi::OsStr, strictness : NameStrictness)
+  -> Result <(), FileError>
+{
+  check_file_name (file_name, strictness).map_err (FileError::InvalidFileName)
+}
+
+/// Rewrites an arbitrary string into a file name that is valid at the given
+/// strictness.
+///
+/// Disallowed characters are replaced with `_`, trailing dots and spaces are removed,
+/// reserved device names are prefixed with `_` and the name is truncated to
+/// [`FILE_NAME_MAX_BYTES`](constant.FILE_NAME_MAX_BYTES.html) bytes at a character
+/// boundary. An empty result, `.` or `..` becomes `_`.
+///
+/// # Examples
+///
+/// ```
+/// # use rs_utils::file::{sanitize_file_name, NameStrictness};
+/// assert_eq!(sanitize_file_name ("a/b: c?.txt", NameStrictness::Posix),
+///   "a_b: c?.txt");
+/// assert_eq!(sanitize_file_name ("a/b: c?.txt", NameStrictness::CrossPlatform),
+///   "a_b_ c_.txt");
+/// assert_eq!(sanitize_file_name ("a/b: c?.txt", NameStrictness::Portable),
+///   "a_b__c_.txt");
+/// assert_eq!(sanitize_file_name ("CON.txt ", NameStrictness::CrossPlatform),
+///   "_CON.txt");
+/// assert_eq!(sanitize_file_name ("..", NameStrictness::Posix), "_");
+/// ```
+
+pub fn sanitize_file_name (name : &str, strictness : NameStrictness) -> String {
+  let mut sanitized = name.chars().enumerate().map (|(i, c)| {
+    let invalid = match strictness {
+      NameStrictness::Posix => c == '\0' || c == '/',
+      NameStrictness::CrossPlatform => c == '\0' || c.is_control() ||
+        WINDOWS_RESERVED_CHARS.contains (&c),
+      NameStrictness::Portable => !is_portable (c) || (i == 0 && c == '-')
+    };
+    if invalid { '_' } else { c }
+  }).collect::<String>();
+  if strictness != NameStrictness::Posix {
+    trim_trailing_dots_and_spaces (&mut sanitized);
+    if is_reserved_name (&sanitized) {
+      sanitized.insert (0, '_');
+    }
+  }
+  while sanitized.len() > FILE_NAME_MAX_BYTES {
+    sanitized.pop();
+  }
+  if strictness != NameStrictness::Posix {
+    trim_trailing_dots_and_spaces (&mut sanitized);
+  }
+  if sanitized.is_empty() || sanitized == "." || sanitized == ".." {
+    return "_".to_string()
+  }
+  sanitized
+}
+
+fn check_file_name (file_name : &ffi::OsStr, strictness : NameStrictness)
+  -> Result <(), FileNameRule>
+{
+  let bytes = file_name.as_encoded_bytes();
+  if bytes.is_empty() {
+    return Err (FileNameRule::Empty)
+  }
+  if bytes == b"." || bytes == b".." {
+    return Err (FileNameRule::DotName)
+  }
+  if bytes.contains (&b'\0') {
+    return Err (FileNameRule::Nul)
+  }
+  if bytes.contains (&b'/') {
+    return Err (FileNameRule::Separator)
+  }
+  if bytes.len() > FILE_NAME_MAX_BYTES {
+    return Err (FileNameRule::TooLong)
+  }
+  if strictness == NameStrictness::Posix {
+    return Ok (())
+  }
+  let name = file_name.to_str().ok_or (FileNameRule::InvalidUnicode)?;
+  if let Some (c) = name.chars().find (|c| c.is_control()) {
+    return Err (FileNameRule::ControlCharacter (c))
+  }
+  if let Some (c) = name.chars().find (|c| WINDOWS_RESERVED_CHARS.contains (c)) {
+    return Err (FileNameRule::ReservedCharacter (c))
+  }
+  if name.ends_with (['.', ' ']) {
+    return Err (FileNameRule::TrailingDotOrSpace)
+  }
+  if is_reserved_name (name) {
+    return Err (FileNameRule::ReservedName)
+  }
+  if strictness == NameStrictness::CrossPlatform {
+    return Ok (())
+  }
+  if let Some (c) = name.chars().find (|c| !is_portable (*c)) {
+    return Err (FileNameRule::NonPortableCharacter (c))
+  }
+  if name.starts_with ('-') {
+    return Err (FileNameRule::LeadingHyphen)
+  }
+  Ok (())
+}
+
+const fn is_portable (c : char) -> bool {
+  c.is_ascii_alphanumeric() || matches!(c, '.' | '_' | '-')
+}
+
+/// Windows reserves device names followed by nothing or by an extension
+fn is_reserved_name (name : &str) -> bool {
+  let stem = name.split ('.').next().unwrap_or_default().trim_end_matches (' ');
+  WINDOWS_RESERVED_NAMES.iter().any (|reserved| reserved.eq_ignore_ascii_case (stem))
+}
+
+fn trim_trailing_dots_and_spaces (name : &mut String) {
+  name.truncate (name.trim_end_matches (['.', ' ']).len());
+}
+
+impl fmt::Display for FileNameRule {
+  fn fmt (&self, f : &mut fmt::Formatter) -> fmt::Result {
+    match self {
+      FileNameRule::Empty                    => write!(f, "empty"),
+      FileNameRule::DotName                  => write!(f, "dot name"),
+      FileNameRule::Nul                      => write!(f, "contains NUL"),
+      FileNameRule::Separator                => write!(f, "contains a separator"),
+      FileNameRule::TooLong                  => write!(f, "too long"),
+      FileNameRule::InvalidUnicode           => write!(f, "invalid unicode"),
+      FileNameRule::ControlCharacter (c)     =>
+        write!(f, "control character {c:?}"),
+      FileNameRule::ReservedCharacter (c)    =>
+        write!(f, "reserved character {c:?}"),
+      FileNameRule::TrailingDotOrSpace       => write!(f, "trailing dot or space"),
+      FileNameRule::ReservedName             => write!(f, "reserved name"),
+      FileNameRule::NonPortableCharacter (c) =>
+        write!(f, "non-portable character {c:?}"),
+      FileNameRule::LeadingHyphen            => write!(f, "leading hyphen")
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  const STRICTNESS : [NameStrictness; 3] = [
+    NameStrictness::Posix, NameStrictness::CrossPlatform, NameStrictness::Portable
+  ];
+
+  #[quickcheck_macros::quickcheck]
+  fn prop_sanitized_names_are_valid (name : String) -> bool {
+    STRICTNESS.iter().all (|strictness| validate_file_name (
+      ffi::OsStr::new (&sanitize_file_name (&name, *strictness)), *strictness
+    ).is_ok())
+  }
+
+  #[test]
+  fn rules() {
+    let rule = |name : &str, strictness|
+      check_file_name (ffi::OsStr::new (name), strictness).err();
+    let long = "x".repeat (FILE_NAME_MAX_BYTES + 1);
+    // each name fails the rule from the given strictness index on
+    for (name, from, expected) in [
+      ("",            0, FileNameRule::Empty),
+      ("..",          0, FileNameRule::DotName),
+      ("a\0b",        0, FileNameRule::Nul),
+      ("a/b",         0, FileNameRule::Separator),
+      (long.as_str(), 0, FileNameRule::TooLong),
+      ("a\tb",        1, FileNameRule::ControlCharacter ('\t')),
+      ("a\\b",        1, FileNameRule::ReservedCharacter ('\\')),
+      ("name ",       1, FileNameRule::TrailingDotOrSpace),
+      ("Lpt1.tar.gz", 1, FileNameRule::ReservedName),
+      ("ümlaut",      2, FileNameRule::NonPortableCharacter ('ü')),
+      ("-rf",         2, FileNameRule::LeadingHyphen)
+    ] {
+      for (i, strictness) in STRICTNESS.into_iter().enumerate() {
+        assert_eq!(rule (name, strictness), (i >= from).then_some (expected),
+          "{name:?} {strictness:?}");
+      }
+    }
+    for strictness in STRICTNESS {
+      assert_eq!(rule (".hidden-file_1.txt", strictness), None);
+    }
+  }
+
+  #[cfg(unix)]
+  #[test]
+  fn non_unicode() {
+    use std::os::unix::ffi::OsStrExt;
+    let name = ffi::OsStr::from_bytes (b"\xff");
+    assert_eq!(check_file_name (name, NameStrictness::Posix), Ok (()));
+    assert_eq!(check_file_name (name, NameStrictness::Portable),
+      Err (FileNameRule::InvalidUnicode));
+  }
+
+  #[test]
+  fn truncation() {
+    let name = format!("{}é.", "x".repeat (FILE_NAME_MAX_BYTES - 1));
+    assert_eq!(sanitize_file_name (&name, NameStrictness::Posix),
+      "x".repeat (FILE_NAME_MAX_BYTES - 1));
+  }
+}
@@ -0,0 +1,188 @@
+//! Options for opening files with path validation and parent directory creation
+
+use std::{fs, path};
+#[cfg(unix)]
+use std::os::unix::fs::OpenOptionsExt;
+
+use super::{ensure_parent_dir, is_file, FileError};
+
+/// A wrapper around `fs::OpenOptions` that refuses paths that can not be files
+/// (&#x261e; see [`is_file`](../fn.is_file.html)) and recursively creates parent
+/// directories before opening.
+///
+/// The default options create a new file in append mode, as
+/// [`file_new_append`](../fn.file_new_append.html) does. On Unix, files are always
+/// opened with `O_CLOEXEC` by the standard library; further flags can be added with
+/// [`custom_flags`](#method.custom_flags).
+///
+/// The options can be passed to
+/// [`IncrementalNamer::file_new_with`](
+/// ../incremental/struct.IncrementalNamer.html#method.file_new_with) to open
+/// incrementally named files with them.
+///
+/// # Examples
+///
+/// ```
+/// # use std::io::{Read, Seek, Write};
+/// # use rs_utils::file::FileOptions;
+/// let temp_dir  = tempfile::Builder::new().prefix ("tmp").tempdir().unwrap();
+/// let file_path = temp_dir.path().join ("secrets/token");
+/// let mut options = FileOptions::new();
+/// options.read (true);
+/// #[cfg(unix)]
+/// options.mode (0o600);
+/// let mut file = options.open (&file_path).unwrap();
+/// file.write_all (b"hunter2").unwrap();
+/// file.rewind().unwrap();
+/// let mut contents = String::new();
+/// file.read_to_string (&mut contents).unwrap();
+/// assert_eq!(contents, "hunter2");
+/// # #[cfg(unix)] {
+/// use std::os::unix::fs::PermissionsExt;
+/// let mode = file.metadata().unwrap().permissions().mode();
+/// assert_eq!(mode & 0o777, 0o600);
+/// # }
+/// assert!(options.open (&file_path).is_err());
+/// ```
+
+#[derive(Clone, Debug)]
+pub struct FileOptions {
+  options        : fs::OpenOptions,
+  create_parents : bool
+}
+
+impl FileOptions {
+  /// Options to create a new file in append mode, creating parent directories
+  pub fn new() -> Self {
+    let mut options = fs::OpenOptions::new();
+    options.append (true).create_new (true);
+    FileOptions { options, create_parents: true }
+  }
+
+  /// Options with every mode disabled, equivalent to `fs::OpenOptions::new`, creating
+  /// parent directories
+  pub fn empty() -> Self {
+    FileOptions { options: fs::OpenOptions::new(), create_parents: true }
+  }
+
+  /// Open an existing file or create it, truncating it and opening it for writing
+  pub fn truncate_write() -> Self {
+    let mut options = Self::empty();
+    options.write (true).truncate (true).create (true);
+    options
+  }
+
+  /// Open an existing file or create it, in append mode
+  pub fn open_or_create_append() -> Self {
+    let mut options = Self::empty();
+    options.append (true).create (true);
+    options
+  }
+
+  pub fn read (&mut self, read : bool) -> &mut Self {
+    self.options.read (read);
+    self
+  }
+
+  pub fn write (&mut self, write : bool) -> &mut Self {
+    self.options.write (write);
+    self
+  }
+
+  pub fn append (&mut self, append : bool) -> &mut Self {
+    self.options.append (append);
+    self
+  }
+
+  pub fn truncate (&mut self, truncate : bool) -> &mut Self {
+    self.options.truncate (truncate);
+    self
+  }
+
+  pub fn create (&mut self, create : bool) -> &mut Self {
+    self.options.create (create);
+    self
+  }
+
+  pub fn create_new (&mut self, create_new : bool) -> &mut Self {
+    self.options.create_new (create_new);
+    self
+  }
+
+  /// Whether missing parent directories are created before opening
+  pub const fn create_parents (&mut self, create_parents : bool) -> &mut Self {
+    self.create_parents = create_parents;
+    self
+  }
+
+  /// Permission bits used when a new file is created
+  #[cfg(unix)]
+  #[cfg_attr(docsrs, doc(cfg(unix)))]
+  pub fn mode (&mut self, mode : u32) -> &mut Self {
+    self.options.mode (mode);
+    self
+  }
+
+  /// Additional flags passed to `open(2)`
+  #[cfg(unix)]
+  #[cfg_attr(docsrs, doc(cfg(unix)))]
+  pub fn custom_flags (&mut self, flags : i32) -> &mut Self {
+    self.options.custom_flags (flags);
+    self
+  }
+
+  /// Opens the file at the given path with these options.
+  ///
+  /// # Errors
+  ///
+  /// - Not a file (&#x261e; see [`is_file`](../fn.is_file.html))
+  /// - Parent not a directory (&#x261e; see
+  ///   [`ensure_parent_dir`](../classify/fn.ensure_parent_dir.html))
+  /// - I/O errors from creating parent directories or opening the file
+
+  pub fn open (&self, file_path : &path::Path) -> Result <fs::File, FileError> {
+    if !is_file (file_path)? {
+      return Err (FileError::NotAFile)
+    }
+    if self.create_parents {
+      ensure_parent_dir (file_path)?;
+    }
+    Ok (self.options.open (file_path)?)
+  }
+}
+
+impl Default for FileOptions {
+  fn default() -> Self {
+    Self::new()
+  }
+}
+
+impl From <fs::OpenOptions> for FileOptions {
+  fn from (options : fs::OpenOptions) -> Self {
+    FileOptions { options, create_parents: true }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use std::io::Write;
+  use super::*;
+
+  #[test]
+  fn presets() {
+    let temp_dir  = tempfile::Builder::new().prefix ("tmp").tempdir().unwrap();
+    let file_path = temp_dir.path().join ("a/b/file");
+    FileOptions::open_or_create_append().open (&file_path).unwrap()
+      .write_all (b"abc").unwrap();
+    FileOptions::open_or_create_append().open (&file_path).unwrap()
+      .write_all (b"def").unwrap();
+    assert_eq!(fs::read_to_string (&file_path).unwrap(), "abcdef");
+    FileOptions::truncate_write().open (&file_path).unwrap().write_all (b"g").unwrap();
+    assert_eq!(fs::read_to_string (&file_path).unwrap(), "g");
+    assert!(matches!(FileOptions::new().open (&temp_dir.path().join ("a/b/")),
+      Err (FileError::NotAFile)));
+    let e = FileOptions::empty().read (true).create_parents (false)
+      .open (&temp_dir.path().join ("c/file")).unwrap_err();
+    assert_eq!(e.kind(), std::io::ErrorKind::NotFound);
+  }
+}
@@ -0,0 +1,369 @@
+//! Lock files recording the id of the process holding them
+
+use std::{fmt, fs, io, path, process, thread, time};
+use std::io::{Read, Write};
+
+use super::{file_new_append, FileError};
+
+/// Age after which a lock file that can not be parsed is considered stale rather than
+/// still being written
+pub const PID_LOCK_GRACE : time::Duration = time::Duration::from_secs (2);
+
+/// What [`PidLock::acquire`](struct.PidLock.html#method.acquire) does when the lock
+/// file is held by a live process
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub enum PidLockConflict {
+  /// Fail with [`FileError::Locked`](../enum.FileError.html#variant.Locked)
+  #[default]
+  Fail,
+  /// Retry until the lock file is released or becomes stale, failing with
+  /// `io::ErrorKind::TimedOut` after the given duration if any
+  Wait (Option <time::Duration>),
+  /// Remove the lock file and take it over
+  Steal
+}
+
+/// The contents of a lock file: the process id, the start time of the lock since the
+/// Unix epoch in seconds with a nanosecond fraction, and on Linux the start time of the
+/// process in clock ticks since boot (field 22 of `/proc/<pid>/stat`), one per line
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct PidLockInfo {
+  pub pid        : u32,
+  pub started    : time::Duration,
+  pub proc_start : Option <u64>
+}
+
+/// A lock file ensuring that only one process at a time works on a resource such as an
+/// output directory.
+///
+/// The lock file is created with `create_new` semantics by
+/// [`file_new_append`](../fn.file_new_append.html) and contains a
+/// [`PidLockInfo`](struct.PidLockInfo.html) for the current process. If it already
+/// exists, the holder is checked: on Linux, a lock is stale if `/proc/<pid>` does not
+/// exist, the process is a zombie, or the process start time differs from the one
+/// recorded (the process id was reused). On other platforms a lock is only stale if
+/// it can not be parsed. Stale lock files are removed and creation is retried; live
+/// ones are handled according to the [`PidLockConflict`](enum.PidLockConflict.html).
+///
+/// The lock file is removed when the `PidLock` is released or dropped, unless it has
+/// been stolen by another process in the meantime. On Unix, lock files are removed
+/// under an advisory lock on the open file and only while the path still refers to it,
+/// so of several processes taking over the same stale lock file only one succeeds.
+///
+/// # Examples
+///
+/// ```
+/// # use rs_utils::file::{FileError, PidLock, PidLockConflict};
+/// let temp_dir  = tempfile::Builder::new().prefix ("tmp").tempdir().unwrap();
+/// let lock_path = temp_dir.path().join ("out/.lock");
+/// let lock = PidLock::acquire (&lock_path, PidLockConflict::Fail).unwrap();
+/// assert_eq!(lock.info().pid, std::process::id());
+/// let e = PidLock::acquire (&lock_path, PidLockConflict::Fail).unwrap_err();
+/// assert!(matches!(e, FileError::Locked { pid } if pid == std::process::id()));
+/// drop (lock);
+/// assert!(!lock_path.exists());
+/// ```
+
+#[derive(Debug)]
+pub struct PidLock {
+  path     : path::PathBuf,
+  info     : PidLockInfo,
+  released : bool
+}
+
+impl PidLockInfo {
+  /// Info for the current process, started now
+  pub fn current() -> Self {
+    let started = time::SystemTime::now().duration_since (time::UNIX_EPOCH)
+      .unwrap_or_default();
+    let pid = process::id();
+    PidLockInfo { pid, started, proc_start: proc_start_time ("self") }
+  }
+
+  /// Reads the lock file at the given path, returning `None` if it does not exist.
+  ///
+  /// # Errors
+  ///
+  /// - `io::ErrorKind::InvalidData` if the contents can not be parsed
+  /// - I/O errors from reading the file
+
+  pub fn read (file_path : &path::Path) -> Result <Option <Self>, FileError> {
+    match fs::read_to_string (file_path) {
+      Ok (contents) => Self::parse (&contents).map (Some)
+        .ok_or_else (|| io::Error::from (io::ErrorKind::InvalidData).into()),
+      Err (e) if e.kind() == io::ErrorKind::NotFound => Ok (None),
+      Err (e) => Err (e.into())
+    }
+  }
+
+  /// Whether the process that wrote this info is known to be gone; always false on
+  /// platforms other than Linux
+  pub fn is_stale (&self) -> bool {
+    #[cfg(target_os = "linux")]
+    {
+      let stat = match fs::read_to_string (format!("/proc/{}/stat", self.pid)) {
+        Ok (stat) => stat,
+        Err (e) => return e.kind() == io::ErrorKind::NotFound
+      };
+      let fields = stat_fields (&stat);
+      if matches!(fields.first(), Some (&("Z" | "X"))) {
+        return true
+      }
+      let start = fields.get (19).and_then (|start| start.parse::<u64>().ok());
+      matches!((self.proc_start, start), (Some (recorded), Some (start))
+        if recorded != start)
+    }
+    #[cfg(not(target_os = "linux"))]
+    false
+  }
+
+  fn parse (contents : &str) -> Option <Self> {
+    let mut lines = contents.lines();
+    let pid     = lines.next()?.trim().parse().ok()?;
+    let (secs, nanos) = lines.next()?.trim().split_once ('.')?;
+    let started = time::Duration::new (secs.parse().ok()?, nanos.parse().ok()?);
+    let proc_start = match lines.next().map (str::trim) {
+      None | Some ("") => None,
+      Some (start) => Some (start.parse().ok()?)
+    };
+    Some (PidLockInfo { pid, started, proc_start })
+  }
+}
+
+impl fmt::Display for PidLockInfo {
+  fn fmt (&self, f : &mut fmt::Formatter) -> fmt::Result {
+    writeln!(f, "{}", self.pid)?;
+    writeln!(f, "{}.{:09}", self.started.as_secs(), self.started.subsec_nanos())?;
+    if let Some (proc_start) = self.proc_start {
+      writeln!(f, "{proc_start}")?;
+    }
+    Ok (())
+  }
+}
+
+impl PidLock {
+  /// Creates the lock file at the given path, recursively creating parent
+  /// directories, removing stale lock files and handling live ones according to
+  /// `conflict`.
+  ///
+  /// # Errors
+  ///
+  /// - Locked: the lock file is held by a live process and `conflict` is `Fail`
+  /// - `io::ErrorKind::TimedOut` if the lock was not released in time
+  /// - Errors from [`file_new_append`](../fn.file_new_append.html) other than an
+  ///   existing file
+  /// - I/O errors from reading, removing or writing the lock file
+
+  pub fn acquire (lock_path : &path::Path, conflict : PidLockConflict)
+    -> Result <Self, FileError>
+  {
+    const MAX_BACKOFF : time::Duration = time::Duration::from_millis (50);
+    let start = time::Instant::now();
+    let mut backoff = time::Duration::from_millis (1);
+    loop {
+      match file_new_append (lock_path) {
+        Ok (mut file) => {
+          let info = PidLockInfo::current();
+          let written = file.write_all (info.to_string().as_bytes())
+            .and_then (|()| file.sync_data());
+          if let Err (e) = written {
+            let _ = fs::remove_file (lock_path);
+            return Err (e.into())
+          }
+          return Ok (PidLock { path: lock_path.to_path_buf(), info, released: false })
+        }
+        Err (e) if e.kind() == io::ErrorKind::AlreadyExists => {}
+        Err (e) => return Err (e)
+      }
+      let contents = match fs::read_to_string (lock_path) {
+        Ok (contents) => contents,
+        Err (e) if e.kind() == io::ErrorKind::NotFound => continue,
+        Err (e) => return Err (e.into())
+      };
+      let holder = PidLockInfo::parse (&contents);
+      let stale  = match holder {
+        Some (holder) => holder.is_stale(),
+        None => match fs::metadata (lock_path).and_then (|m| m.modified()) {
+          Ok (modified) => modified.elapsed().is_ok_and (|age| age >= PID_LOCK_GRACE),
+          Err (e) if e.kind() == io::ErrorKind::NotFound => continue,
+          Err (e) => return Err (e.into())
+        }
+      };
+      if stale || conflict == PidLockConflict::Steal {
+        remove_if_unchanged (lock_path, &contents)?;
+        continue
+      }
+      // a lock file that can not be parsed yet is waited for in every mode
+      if let Some (holder) = holder {
+        match conflict {
+          PidLockConflict::Fail => return Err (FileError::Locked { pid: holder.pid }),
+          PidLockConflict::Wait (Some (timeout)) if start.elapsed() >= timeout =>
+            return Err (io::Error::from (io::ErrorKind::TimedOut).into()),
+          PidLockConflict::Wait (_) | PidLockConflict::Steal => {}
+        }
+      }
+      thread::sleep (backoff);
+      backoff = (backoff * 2).min (MAX_BACKOFF);
+    }
+  }
+
+  pub fn path (&self) -> &path::Path {
+    &self.path
+  }
+
+  pub const fn info (&self) -> &PidLockInfo {
+    &self.info
+  }
+
+  /// Removes the lock file unless it has been stolen.
+  ///
+  /// # Errors
+  ///
+  /// I/O errors from reading or removing the lock file
+
+  pub fn release (mut self) -> Result <(), FileError> {
+    self.remove()
+  }
+
+  fn remove (&mut self) -> Result <(), FileError> {
+    if self.released {
+      return Ok (())
+    }
+    self.released = true;
+    remove_if_unchanged (&self.path, &self.info.to_string())
+  }
+}
+
+impl Drop for PidLock {
+  fn drop (&mut self) {
+    let _ = self.remove();
+  }
+}
+
+/// Removes the lock file if it still has the given contents; on Unix the open file is
+/// locked while it is compared and removed
+fn remove_if_unchanged (lock_path : &path::Path, contents : &str)
+  -> Result <(), FileError>
+{
+  let result = fs::File::open (lock_path).and_then (|file| {
+    #[cfg(unix)]
+    file.lock()?;
+    let mut current = Vec::new();
+    (&file).read_to_end (&mut current)?;
+    if current == contents.as_bytes() && is_linked (&file, lock_path)? {
+      fs::remove_file (lock_path)?;
+    }
+    Ok (())
+  });
+  match result {
+    Err (e) if e.kind() != io::ErrorKind::NotFound => Err (e.into()),
+    _ => Ok (())
+  }
+}
+
+/// Whether `lock_path` still refers to the open file, i.e. it has not been removed and
+/// recreated by another process
+#[cfg(unix)]
+fn is_linked (file : &fs::File, lock_path : &path::Path) -> io::Result <bool> {
+  use std::os::unix::fs::MetadataExt;
+  let (linked, open) = (fs::metadata (lock_path)?, file.metadata()?);
+  Ok ((linked.dev(), linked.ino()) == (open.dev(), open.ino()))
+}
+
+#[cfg(not(unix))]
+#[expect(clippy::unnecessary_wraps)]
+const fn is_linked (_file : &fs::File, _lock_path : &path::Path) -> io::Result <bool> {
+  Ok (true)
+}
+
+/// Start time of the process in clock ticks since boot, where `pid` is a process id or
+/// `self`
+#[cfg(target_os = "linux")]
+fn proc_start_time (pid : &str) -> Option <u64> {
+  let stat = fs::read_to_string (format!("/proc/{pid}/stat")).ok()?;
+  stat_fields (&stat).get (19)?.parse().ok()
+}
+
+#[cfg(not(target_os = "linux"))]
+const fn proc_start_time (_pid : &str) -> Option <u64> {
+  None
+}
+
+/// Fields of `/proc/<pid>/stat` following the command name, starting with the state
+#[cfg(target_os = "linux")]
+fn stat_fields (stat : &str) -> Vec <&str> {
+  stat.rfind (')')
+    .map_or_else (Vec::new, |i| stat[i + 1..].split_whitespace().collect())
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn conflicts() {
+    let temp_dir  = tempfile::Builder::new().prefix ("tmp").tempdir().unwrap();
+    let lock_path = temp_dir.path().join ("lock");
+    let lock = PidLock::acquire (&lock_path, PidLockConflict::Fail).unwrap();
+    assert_eq!(PidLockInfo::read (&lock_path).unwrap(), Some (*lock.info()));
+    let e = PidLock::acquire (&lock_path,
+      PidLockConflict::Wait (Some (time::Duration::from_millis (20)))).unwrap_err();
+    assert_eq!(e.kind(), io::ErrorKind::TimedOut);
+    let stolen = PidLock::acquire (&lock_path, PidLockConflict::Steal).unwrap();
+    lock.release().unwrap();
+    assert!(lock_path.exists());
+    let waiter = thread::spawn ({
+      let lock_path = lock_path.clone();
+      move || PidLock::acquire (&lock_path, PidLockConflict::Wait (None)).unwrap()
+    });
+    thread::sleep (time::Duration::from_millis (20));
+    drop (stolen);
+    let lock = waiter.join().unwrap();
+    assert_eq!(PidLockInfo::read (&lock_path).unwrap(), Some (*lock.info()));
+    drop (lock);
+    assert_eq!(PidLockInfo::read (&lock_path).unwrap(), None);
+  }
+
+  #[cfg(target_os = "linux")]
+  #[test]
+  fn stale_locks() {
+    let temp_dir  = tempfile::Builder::new().prefix ("tmp").tempdir().unwrap();
+    let lock_path = temp_dir.path().join ("lock");
+    let mut child = process::Command::new ("true").spawn().unwrap();
+    let dead = PidLockInfo { pid: child.id(), .. PidLockInfo::current() };
+    child.wait().unwrap();
+    let reused = PidLockInfo { proc_start: Some (0), .. PidLockInfo::current() };
+    for holder in [dead, reused] {
+      assert!(holder.is_stale());
+      fs::write (&lock_path, holder.to_string()).unwrap();
+      let lock = PidLock::acquire (&lock_path, PidLockConflict::Fail).unwrap();
+      assert!(!lock.info().is_stale());
+    }
+  }
+
+  #[cfg(target_os = "linux")]
+  #[test]
+  fn concurrent_takeover() {
+    use std::sync::atomic;
+    let temp_dir  = tempfile::Builder::new().prefix ("tmp").tempdir().unwrap();
+    let lock_path = temp_dir.path().join ("lock");
+    let mut child = process::Command::new ("true").spawn().unwrap();
+    let dead = PidLockInfo { pid: child.id(), .. PidLockInfo::current() };
+    child.wait().unwrap();
+    let holders = atomic::AtomicUsize::new (0);
+    for _ in 0..10 {
+      fs::write (&lock_path, dead.to_string()).unwrap();
+      thread::scope (|scope| for _ in 0..8 {
+        scope.spawn (|| {
+          let wait = PidLockConflict::Wait (None);
+          let lock = PidLock::acquire (&lock_path, wait).unwrap();
+          assert_eq!(holders.fetch_add (1, atomic::Ordering::SeqCst), 0);
+          thread::sleep (time::Duration::from_millis (1));
+          holders.fetch_sub (1, atomic::Ordering::SeqCst);
+          lock.release().unwrap();
+        });
+      });
+      assert!(!lock_path.exists());
+    }
+  }
+}
@@ -0,0 +1,397 @@
+//! Length-prefixed, checksummed append-only record files
+//!
+//! Each record is stored as a little-endian `u32` payload length, a little-endian `u32`
+//! CRC-32 (IEEE) of the length bytes followed by the payload, and the payload itself. A
+//! torn write can only damage the tail of the file, which a
+//! [`RecordReader`](struct.RecordReader.html) detects and
+//! [`repair_record_file`](fn.repair_record_file.html) truncates. Records can not be
+//! resynchronized after a damaged length, so corruption in the middle of the file, for
+//! example from a media error, hides every record after it as well.
+
+use std::{fmt, fs, io, path};
+
+use super::{file_new_append, write_fmt_record, FileError, FileOptions};
+
+/// Size of the length and checksum that precede each payload
+pub const RECORD_HEADER_LEN : usize = 8;
+/// Largest payload accepted; larger lengths read from a file are treated as corruption
+pub const RECORD_MAX_LEN    : usize = 1 << 28;
+
+/// State of the end of a record file after reading it
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum RecordTail {
+  /// Every byte belongs to a complete, valid record
+  Clean,
+  /// The file ends inside the record starting at `offset`
+  Truncated { offset : u64 },
+  /// The record starting at `offset` has an invalid length or checksum
+  Corrupt { offset : u64 }
+}
+
+/// Appends records to a file; each call to `append` writes one record with a single
+/// `write_all`.
+///
+/// `RecordWriter` also implements `io::Write`, where each call to `write`, `write!` or
+/// `writeln!` is one record.
+///
+/// # Examples
+///
+/// ```
+/// # use rs_utils::file::{RecordReader, RecordTail, RecordWriter};
+/// let temp_dir  = tempfile::Builder::new().prefix ("tmp").tempdir().unwrap();
+/// let file_path = temp_dir.path().join ("journal");
+/// let mut writer = RecordWriter::create_new (&file_path).unwrap();
+/// writer.append (b"first").unwrap();
+/// writer.append (b"second").unwrap();
+/// let mut reader = RecordReader::open (&file_path).unwrap();
+/// let records = reader.by_ref().collect::<Result <Vec <_>, _>>().unwrap();
+/// assert_eq!(records, [b"first".to_vec(), b"second".to_vec()]);
+/// assert_eq!(reader.tail(), Some (RecordTail::Clean));
+/// ```
+
+#[derive(Debug)]
+pub struct RecordWriter {
+  file : fs::File
+}
+
+/// Iterates the records of a file, stopping at the first incomplete or corrupt record.
+///
+/// Only I/O errors are returned as items; whether the file ended cleanly is reported
+/// by [`tail`](#method.tail) once the iterator is exhausted.
+#[derive(Debug)]
+pub struct RecordReader <R> {
+  reader    : R,
+  valid_len : u64,
+  tail      : Option <RecordTail>
+}
+
+impl RecordWriter {
+  /// Wraps a file opened in append mode
+  pub const fn new (file : fs::File) -> Self {
+    RecordWriter { file }
+  }
+
+  /// Creates a new record file as by [`file_new_append`](../fn.file_new_append.html).
+  ///
+  /// # Errors
+  ///
+  /// Errors from [`file_new_append`](../fn.file_new_append.html)
+
+  pub fn create_new (file_path : &path::Path) -> Result <Self, FileError> {
+    Ok (Self::new (file_new_append (file_path)?))
+  }
+
+  /// Opens a record file for appending, creating it if missing, after truncating any
+  /// incomplete or corrupt tail left by an interrupted write.
+  ///
+  /// Returns the writer together with the tail that was found. Like
+  /// [`repair_record_file`](fn.repair_record_file.html), this discards everything from
+  /// the first invalid record on, including valid records after a corruption in the
+  /// middle of the file.
+  ///
+  /// # Examples
+  ///
+  /// ```
+  /// # use std::io::Write;
+  /// # use rs_utils::file::{RecordReader, RecordTail, RecordWriter};
+  /// let temp_dir  = tempfile::Builder::new().prefix ("tmp").tempdir().unwrap();
+  /// let file_path = temp_dir.path().join ("journal");
+  /// let (mut writer, _) = RecordWriter::open_for_append (&file_path).unwrap();
+  /// writer.append (b"complete").unwrap();
+  /// writer.file().write_all (&[5, 0, 0]).unwrap();  // torn header
+  /// drop (writer);
+  /// let (mut writer, tail) = RecordWriter::open_for_append (&file_path).unwrap();
+  /// assert_eq!(tail, RecordTail::Truncated { offset: 16 });
+  /// writer.append (b"resumed").unwrap();
+  /// let records = RecordReader::open (&file_path).unwrap()
+  ///   .collect::<Result <Vec <_>, _>>().unwrap();
+  /// assert_eq!(records, [b"complete".to_vec(), b"resumed".to_vec()]);
+  /// ```
+  ///
+  /// # Errors
+  ///
+  /// - Errors from [`FileOptions::open`](../open/struct.FileOptions.html#method.open)
+  /// - I/O errors from reading or truncating the file
+
+  pub fn open_for_append (file_path : &path::Path)
+    -> Result <(Self, RecordTail), FileError>
+  {
+    let file = FileOptions::open_or_create_append().read (true).open (file_path)?;
+    let tail = repair (&file)?;
+    Ok ((Self::new (file), tail))
+  }
+
+  pub const fn file (&self) -> &fs::File {
+    &self.file
+  }
+
+  /// Appends one record.
+  ///
+  /// # Errors
+  ///
+  /// - Record too long: the payload is longer than
+  ///   [`RECORD_MAX_LEN`](constant.RECORD_MAX_LEN.html)
+  /// - I/O errors from writing
+
+  pub fn append (&mut self, payload : &[u8]) -> Result <(), FileError> {
+    use io::Write;
+    if payload.len() > RECORD_MAX_LEN {
+      return Err (FileError::RecordTooLong { len: payload.len() })
+    }
+    #[expect(clippy::cast_possible_truncation)]
+    let len = (payload.len() as u32).to_le_bytes();
+    let crc = crc32_update (crc32_update (!0, &len), payload) ^ !0;
+    let mut buf = Vec::with_capacity (RECORD_HEADER_LEN + payload.len());
+    buf.extend_from_slice (&len);
+    buf.extend_from_slice (&crc.to_le_bytes());
+    buf.extend_from_slice (payload);
+    self.file.write_all (&buf)?;
+    Ok (())
+  }
+}
+
+impl io::Write for RecordWriter {
+  fn write (&mut self, buf : &[u8]) -> io::Result <usize> {
+    self.append (buf)?;
+    Ok (buf.len())
+  }
+
+  fn write_fmt (&mut self, args : fmt::Arguments) -> io::Result <()> {
+    write_fmt_record (self, args)
+  }
+
+  fn flush (&mut self) -> io::Result <()> {
+    self.file.flush()
+  }
+}
+
+impl RecordReader <io::BufReader <fs::File>> {
+  /// Opens a record file for reading.
+  ///
+  /// # Errors
+  ///
+  /// I/O errors from opening the file
+
+  pub fn open (file_path : &path::Path) -> Result <Self, FileError> {
+    Ok (Self::new (io::BufReader::new (fs::File::open (file_path)?)))
+  }
+}
+
+impl <R : io::Read> RecordReader <R> {
+  pub const fn new (reader : R) -> Self {
+    RecordReader { reader, valid_len: 0, tail: None }
+  }
+
+  /// Length of the prefix of the file made up of the valid records read so far
+  pub const fn valid_len (&self) -> u64 {
+    self.valid_len
+  }
+
+  /// How the file ended, once the iterator has returned `None`
+  pub const fn tail (&self) -> Option <RecordTail> {
+    self.tail
+  }
+
+  fn read_record (&mut self) -> Result <Result <Vec <u8>, RecordTail>, FileError> {
+    use io::Read;
+    let offset = self.valid_len;
+    let mut header = [0u8; RECORD_HEADER_LEN];
+    match read_full (&mut self.reader, &mut header)? {
+      0 => return Ok (Err (RecordTail::Clean)),
+      RECORD_HEADER_LEN => {}
+      _ => return Ok (Err (RecordTail::Truncated { offset }))
+    }
+    let (len, crc) = header.split_at (4);
+    let len = u32::from_le_bytes (len.try_into().unwrap());
+    let crc = u32::from_le_bytes (crc.try_into().unwrap());
+    if len as usize > RECORD_MAX_LEN {
+      return Ok (Err (RecordTail::Corrupt { offset }))
+    }
+    // the length may be corrupt, so only allocate for the bytes that are there
+    let mut payload = Vec::new();
+    (&mut self.reader).take (u64::from (len)).read_to_end (&mut payload)?;
+    if payload.len() < len as usize {
+      return Ok (Err (RecordTail::Truncated { offset }))
+    }
+    if crc32_update (crc32_update (!0, &len.to_le_bytes()), &payload) ^ !0 != crc {
+      return Ok (Err (RecordTail::Corrupt { offset }))
+    }
+    self.valid_len += (RECORD_HEADER_LEN + payload.len()) as u64;
+    Ok (Ok (payload))
+  }
+}
+
+impl <R : io::Read> Iterator for RecordReader <R> {
+  type Item = Result <Vec <u8>, FileError>;
+
+  fn next (&mut self) -> Option <Self::Item> {
+    if self.tail.is_some() {
+      return None
+    }
+    match self.read_record() {
+      Ok (Ok (payload)) => Some (Ok (payload)),
+      Ok (Err (tail))   => {
+        self.tail = Some (tail);
+        None
+      }
+      Err (e) => Some (Err (e))
+    }
+  }
+}
+
+/// Truncates an incomplete or corrupt tail from a record file and returns the tail that
+/// was found.
+///
+/// The file is truncated at the first invalid record, so valid records after a
+/// corruption in the middle of the file are discarded as well. To decide before
+/// anything is removed, read the file with a [`RecordReader`](struct.RecordReader.html)
+/// first: its [`tail`](struct.RecordReader.html#method.tail) holds the offset that
+/// repairing would truncate at.
+///
+/// # Errors
+///
+/// I/O errors from opening, reading or truncating the file
+
+pub fn repair_record_file (file_path : &path::Path) -> Result <RecordTail, FileError> {
+  let file = fs::OpenOptions::new().read (true).write (true).open (file_path)?;
+  repair (&file)
+}
+
+fn repair (file : &fs::File) -> Result <RecordTail, FileError> {
+  let mut reader = RecordReader::new (io::BufReader::new (file));
+  for record in reader.by_ref() {
+    record?;
+  }
+  let tail = reader.tail.unwrap_or (RecordTail::Clean);
+  if tail != RecordTail::Clean {
+    file.set_len (reader.valid_len)?;
+    file.sync_data()?;
+  }
+  Ok (tail)
+}
+
+/// Reads until `buf` is full or the reader is at end of file, returning the number of
+/// bytes read
+fn read_full (reader : &mut impl io::Read, buf : &mut [u8]) -> io::Result <usize> {
+  let mut filled = 0;
+  while filled < buf.len() {
+    match reader.read (&mut buf[filled..]) {
+      Ok (0) => break,
+      Ok (n) => filled += n,
+      Err (e) if e.kind() == io::ErrorKind::Interrupted => {}
+      Err (e) => return Err (e)
+    }
+  }
+  Ok (filled)
+}
+
+const CRC32_TABLE : [u32; 256] = {
+  let mut table = [0u32; 256];
+  let mut i = 0;
+  while i < 256 {
+    #[expect(clippy::cast_possible_truncation)]
+    let mut crc = i as u32;
+    let mut bit = 0;
+    while bit < 8 {
+      crc = if crc & 1 == 1 { (crc >> 1) ^ 0xEDB8_8320 } else { crc >> 1 };
+      bit += 1;
+    }
+    table[i] = crc;
+    i += 1;
+  }
+  table
+};
+
+/// Continues a CRC-32 (IEEE) computation; start with `!0` and invert the final value
+fn crc32_update (crc : u32, bytes : &[u8]) -> u32 {
+  bytes.iter().fold (crc, |crc, byte|
+    CRC32_TABLE[((crc ^ u32::from (*byte)) & 0xff) as usize] ^ (crc >> 8))
+}
+
+#[cfg(test)]
+mod tests {
+  use std::io::Write;
+  use super::*;
+
+  #[test]
+  fn crc32_check_value() {
+    assert_eq!(crc32_update (!0, b"123456789") ^ !0, 0xCBF4_3926);
+  }
+
+  #[test]
+  fn corrupt_tail() {
+    let temp_dir  = tempfile::Builder::new().prefix ("tmp").tempdir().unwrap();
+    let file_path = temp_dir.path().join ("journal");
+    let mut writer = RecordWriter::create_new (&file_path).unwrap();
+    for record in [&b"a"[..], b"bb", b"ccc"] {
+      writer.write_all (record).unwrap();
+    }
+    drop (writer);
+    let mut bytes = fs::read (&file_path).unwrap();
+    *bytes.last_mut().unwrap() ^= 1;
+    fs::write (&file_path, &bytes).unwrap();
+    let mut reader = RecordReader::open (&file_path).unwrap();
+    assert_eq!(reader.by_ref().map (Result::unwrap).collect::<Vec <_>>(),
+      [b"a".to_vec(), b"bb".to_vec()]);
+    assert_eq!(reader.tail(), Some (RecordTail::Corrupt { offset: 19 }));
+    assert_eq!(repair_record_file (&file_path).unwrap(),
+      RecordTail::Corrupt { offset: 19 });
+    assert_eq!(fs::metadata (&file_path).unwrap().len(), 19);
+    assert_eq!(repair_record_file (&file_path).unwrap(), RecordTail::Clean);
+  }
+
+  #[test]
+  fn formatted_records() {
+    let temp_dir  = tempfile::Builder::new().prefix ("tmp").tempdir().unwrap();
+    let file_path = temp_dir.path().join ("journal");
+    let mut writer = RecordWriter::create_new (&file_path).unwrap();
+    for i in 0..2 {
+      writeln!(writer, "record {i}").unwrap();
+    }
+    let e = writer.append (&vec![0; RECORD_MAX_LEN + 1]).unwrap_err();
+    assert!(matches!(e, FileError::RecordTooLong { len } if len == RECORD_MAX_LEN + 1));
+    assert_eq!(e.kind(), io::ErrorKind::InvalidInput);
+    let records = RecordReader::open (&file_path).unwrap()
+      .collect::<Result <Vec <_>, _>>().unwrap();
+    assert_eq!(records, [b"record 0\n".to_vec(), b"record 1\n".to_vec()]);
+  }
+
+  #[test]
+  fn truncated_payload() {
+    let temp_dir  = tempfile::Builder::new().prefix ("tmp").tempdir().unwrap();
+    let file_path = temp_dir.path().join ("journal");
+    let mut writer = RecordWriter::create_new (&file_path).unwrap();
+    writer.append (b"abcdef").unwrap();
+    writer.file().set_len (10).unwrap();
+    let mut reader = RecordReader::open (&file_path).unwrap();
+    assert!(reader.next().is_none());
+    assert_eq!(reader.tail(), Some (RecordTail::Truncated { offset: 0 }));
+    // a corrupt length larger than the rest of the file is a truncation, not an
+    // allocation of that size
+    let mut header = u32::try_from (RECORD_MAX_LEN).unwrap().to_le_bytes().to_vec();
+    header.extend_from_slice (&[0; 7]);
+    fs::write (&file_path, header).unwrap();
+    let mut reader = RecordReader::open (&file_path).unwrap();
+    assert!(reader.next().is_none());
+    assert_eq!(reader.tail(), Some (RecordTail::Truncated { offset: 0 }));
+  }
+
+  #[test]
+  fn corrupt_middle() {
+    let temp_dir  = tempfile::Builder::new().prefix ("tmp").tempdir().unwrap();
+    let file_path = temp_dir.path().join ("journal");
+    let mut writer = RecordWriter::create_new (&file_path).unwrap();
+    for record in [&b"a"[..], b"bb", b"ccc"] {
+      writer.append (record).unwrap();
+    }
+    drop (writer);
+    let mut bytes = fs::read (&file_path).unwrap();
+    bytes[9 + RECORD_HEADER_LEN] ^= 1;
+    fs::write (&file_path, &bytes).unwrap();
+    assert_eq!(repair_record_file (&file_path).unwrap(),
+      RecordTail::Corrupt { offset: 9 });
+    let records = RecordReader::open (&file_path).unwrap()
+      .collect::<Result <Vec <_>, _>>().unwrap();
+    assert_eq!(records, [b"a".to_vec()]);
+  }
+}
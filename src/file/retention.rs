@@ -0,0 +1,198 @@
+//! Retention and pruning of incremental file series
+#![expect(clippy::module_name_repetitions)]
+
+use std::{fs, path, time};
+
+use super::{FileError, IncrementalNamer};
+
+/// Which members of an incremental file series are kept by
+/// [`RetentionPolicy::apply`](#method.apply); `None` disables a rule.
+///
+/// Members are ordered by index, with the highest index being the newest. Only regular
+/// files are considered, other entries that match the naming scheme are left alone.
+///
+/// # Examples
+///
+/// ```
+/// # use std::fs; use std::path::Path;
+/// # use rs_utils::file::{file_new_append_incremental, RetentionPolicy};
+/// let temp_dir  = tempfile::Builder::new().prefix ("tmp").tempdir().unwrap();
+/// let file_path = temp_dir.path().join ("log");
+/// for _ in 0..4 {
+///   file_new_append_incremental (&file_path).unwrap();
+/// }
+/// let policy  = RetentionPolicy::default().keep_newest (Some (2)).build();
+/// let dry_run = RetentionPolicy { dry_run: true, .. policy };
+/// let removed = dry_run.prune (&file_path).unwrap();
+/// assert_eq!(removed,
+///   [temp_dir.path().join ("log-0"), temp_dir.path().join ("log-1")]);
+/// assert!(temp_dir.path().join ("log-0").exists());
+/// assert_eq!(policy.prune (&file_path).unwrap(), removed);
+/// assert!(!temp_dir.path().join ("log-0").exists());
+/// assert!(temp_dir.path().join ("log-2").exists());
+/// ```
+
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct RetentionPolicy {
+  /// Keep at most this many of the newest files
+  pub keep_newest     : Option <usize>,
+  /// Remove the oldest files until the newest files total at most this many bytes
+  pub max_total_bytes : Option <u64>,
+  /// Remove files last modified longer ago than this
+  pub max_age         : Option <time::Duration>,
+  /// Only report which files would be removed
+  pub dry_run         : bool
+}
+
+impl RetentionPolicy {
+  pub const fn keep_newest (&mut self, keep_newest : Option <usize>) -> &mut Self {
+    self.keep_newest = keep_newest;
+    self
+  }
+
+  pub const fn max_total_bytes (&mut self, max_total_bytes : Option <u64>)
+    -> &mut Self
+  {
+    self.max_total_bytes = max_total_bytes;
+    self
+  }
+
+  pub const fn max_age (&mut self, max_age : Option <time::Duration>) -> &mut Self {
+    self.max_age = max_age;
+    self
+  }
+
+  pub const fn dry_run (&mut self, dry_run : bool) -> &mut Self {
+    self.dry_run = dry_run;
+    self
+  }
+
+  pub const fn build (&mut self) -> Self {
+    *self
+  }
+
+  /// Applies the policy to the series named by the default
+  /// [`IncrementalNamer`](../incremental/struct.IncrementalNamer.html), as created by
+  /// [`file_new_append_incremental`](../fn.file_new_append_incremental.html).
+  ///
+  /// # Errors
+  ///
+  /// &#x261e; See [`apply`](#method.apply)
+
+  pub fn prune (&self, file_path : &path::Path)
+    -> Result <Vec <path::PathBuf>, FileError>
+  {
+    self.apply (&IncrementalNamer::default(), file_path)
+  }
+
+  /// Finds the members of the series that `namer` generates for `file_path` and removes
+  /// those that are not retained, oldest first.
+  ///
+  /// Returns the paths that were removed, or that would be removed in dry-run mode.
+  ///
+  /// # Errors
+  ///
+  /// - Not a file
+  /// - I/O errors from reading the directory, file metadata, or removing files
+
+  pub fn apply (&self, namer : &IncrementalNamer, file_path : &path::Path)
+    -> Result <Vec <path::PathBuf>, FileError>
+  {
+    self.apply_protected (namer, file_path, usize::MAX)
+  }
+
+  /// Like `apply` but never removes members with an index of at least `protected`;
+  /// they still count towards the limits
+  pub(crate) fn apply_protected (&self,
+    namer : &IncrementalNamer, file_path : &path::Path, protected : usize
+  ) -> Result <Vec <path::PathBuf>, FileError> {
+    let now = time::SystemTime::now();
+    let mut kept        = 0;
+    let mut total_bytes = 0u64;
+    let mut removed     = Vec::new();
+    for (index, path) in namer.series (file_path)?.into_iter().rev() {
+      let metadata = fs::symlink_metadata (&path)?;
+      if !metadata.is_file() {
+        continue
+      }
+      if index >= protected {
+        total_bytes = total_bytes.saturating_add (metadata.len());
+        kept += 1;
+        continue
+      }
+      let expired = self.max_age.is_some_and (|max_age| metadata.modified()
+        .is_ok_and (|modified| now.duration_since (modified)
+          .is_ok_and (|age| age > max_age)));
+      if expired {
+        removed.push (path);
+        continue
+      }
+      total_bytes = total_bytes.saturating_add (metadata.len());
+      if self.keep_newest.is_some_and (|keep| kept >= keep) ||
+        self.max_total_bytes.is_some_and (|max| total_bytes > max)
+      {
+        removed.push (path);
+      } else {
+        kept += 1;
+      }
+    }
+    removed.reverse();
+    if !self.dry_run {
+      for path in &removed {
+        fs::remove_file (path)?;
+      }
+    }
+    Ok (removed)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use std::io::Write;
+  use super::*;
+
+  fn series (sizes : &[usize]) -> (tempfile::TempDir, path::PathBuf) {
+    let temp_dir  = tempfile::Builder::new().prefix ("tmp").tempdir().unwrap();
+    let file_path = temp_dir.path().join ("log");
+    for size in sizes {
+      let (_, mut file) =
+        crate::file::file_new_append_incremental (&file_path).unwrap();
+      file.write_all (&vec![b'x'; *size]).unwrap();
+    }
+    (temp_dir, file_path)
+  }
+
+  #[test]
+  fn max_total_bytes() {
+    let (temp_dir, file_path) = series (&[4, 4, 4, 4]);
+    fs::create_dir (temp_dir.path().join ("log-4")).unwrap();
+    let removed = RetentionPolicy::default().max_total_bytes (Some (9)).build()
+      .prune (&file_path).unwrap();
+    assert_eq!(removed,
+      [temp_dir.path().join ("log-0"), temp_dir.path().join ("log-1")]);
+    assert!(temp_dir.path().join ("log-2").exists());
+    assert!(temp_dir.path().join ("log-4").is_dir());
+  }
+
+  #[test]
+  fn max_age() {
+    let (temp_dir, file_path) = series (&[1, 1]);
+    let mut policy = RetentionPolicy::default();
+    policy.max_age (Some (time::Duration::from_secs (3600)));
+    assert!(policy.prune (&file_path).unwrap().is_empty());
+    fs::File::options().write (true).open (temp_dir.path().join ("log-0")).unwrap()
+      .set_modified (time::SystemTime::now() - time::Duration::from_secs (7200))
+      .unwrap();
+    assert_eq!(policy.prune (&file_path).unwrap(), [temp_dir.path().join ("log-0")]);
+    assert!(temp_dir.path().join ("log-1").exists());
+  }
+
+  #[test]
+  fn keep_zero_removes_all() {
+    let (temp_dir, file_path) = series (&[0, 0, 0]);
+    let removed = RetentionPolicy::default().keep_newest (Some (0)).build()
+      .prune (&file_path).unwrap();
+    assert_eq!(removed.len(), 3);
+    assert_eq!(fs::read_dir (temp_dir.path()).unwrap().count(), 0);
+  }
+}
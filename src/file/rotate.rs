@@ -0,0 +1,491 @@
+//! Size, count and time based file rotation
+
+use std::{fmt, fs, io, path, time};
+
+use super::{
+  write_fmt_record, FileError, IncrementalNamer, RetentionPolicy, SyncPolicy, SyncState
+};
+
+/// Callback invoked after each rotation with the previous and the new file path
+pub type RotateHook = Box <dyn FnMut (&path::Path, &path::Path) + Send>;
+
+/// Callback invoked with the error when applying the retention policy after a rotation
+/// fails
+pub type RetentionErrorHook = Box <dyn FnMut (&FileError) + Send>;
+
+/// Limits that trigger a rotation to the next file; `None` disables a limit
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct RotationPolicy {
+  /// Rotate before a write that would grow the current file beyond this many bytes
+  pub max_bytes   : Option <u64>,
+  /// Rotate after this many writes to the current file
+  pub max_records : Option <u64>,
+  /// Rotate on the first write after the current file has been open this long
+  pub max_age     : Option <time::Duration>
+}
+
+/// An `io::Write` sink that opens files with an
+/// [`IncrementalNamer`](../incremental/struct.IncrementalNamer.html) and rolls over to
+/// the next index when a [`RotationPolicy`](struct.RotationPolicy.html) limit is
+/// reached.
+///
+/// Each call to `write` is treated as one record: it is written to a single file in
+/// full and rotation is only checked between writes, so a record, including a whole
+/// `write!` or `writeln!` call, is never split. A single record larger than
+/// `max_bytes` is written to a new file on its own.
+///
+/// Written data is synced according to the
+/// [`SyncPolicy`](../durable/enum.SyncPolicy.html) set with
+/// [`sync_policy`](#method.sync_policy). Unless the policy is `Never`, the previous
+/// file is synced before each rotation and the current file when the writer is dropped.
+///
+/// # Examples
+///
+/// ```
+/// # use std::io::Write;
+/// # use rs_utils::file::{IncrementalNamer, RotatingFileWriter, RotationPolicy};
+/// let temp_dir = tempfile::Builder::new().prefix ("tmp").tempdir().unwrap();
+/// let policy   = RotationPolicy::default().max_bytes (Some (8)).build();
+/// let mut writer = RotatingFileWriter::new (
+///   &temp_dir.path().join ("log"), IncrementalNamer::default(), policy
+/// ).unwrap();
+/// writer.write_all (b"line 0\n").unwrap();
+/// assert_eq!(writer.path(), temp_dir.path().join ("log-0"));
+/// writer.write_all (b"line 1\n").unwrap();
+/// assert_eq!(writer.path(), temp_dir.path().join ("log-1"));
+/// ```
+
+pub struct RotatingFileWriter {
+  file_path          : path::PathBuf,
+  namer              : IncrementalNamer,
+  policy             : RotationPolicy,
+  file               : fs::File,
+  path               : path::PathBuf,
+  index              : usize,
+  bytes              : u64,
+  records            : u64,
+  opened             : time::Instant,
+  retention          : Option <RetentionPolicy>,
+  on_rotate          : Option <RotateHook>,
+  on_retention_error : Option <RetentionErrorHook>,
+  sync               : SyncState
+}
+
+impl RotationPolicy {
+  pub const fn max_bytes (&mut self, max_bytes : Option <u64>) -> &mut Self {
+    self.max_bytes = max_bytes;
+    self
+  }
+
+  pub const fn max_records (&mut self, max_records : Option <u64>) -> &mut Self {
+    self.max_records = max_records;
+    self
+  }
+
+  pub const fn max_age (&mut self, max_age : Option <time::Duration>) -> &mut Self {
+    self.max_age = max_age;
+    self
+  }
+
+  pub const fn build (&mut self) -> Self {
+    *self
+  }
+}
+
+impl RotatingFileWriter {
+  /// Creates the first file of the series with `namer.file_new_append (file_path)`.
+  ///
+  /// # Errors
+  ///
+  /// Errors from [`IncrementalNamer::file_new_append`](
+  /// ../incremental/struct.IncrementalNamer.html#method.file_new_append)
+
+  pub fn new (
+    file_path : &path::Path, namer : IncrementalNamer, policy : RotationPolicy
+  ) -> Result <Self, FileError> {
+    let (index, path, file) = namer.file_new_append_from (file_path, namer.start)?;
+    Ok (RotatingFileWriter {
+      file_path: file_path.to_path_buf(),
+      namer,
+      policy,
+      file,
+      path,
+      index,
+      bytes:              0,
+      records:            0,
+      opened:             time::Instant::now(),
+      retention:          None,
+      on_rotate:          None,
+      on_retention_error: None,
+      sync:               SyncState::new (SyncPolicy::Never)
+    })
+  }
+
+  /// Resumes the series by reopening its latest file for appending, or creates the
+  /// first file if the series is empty.
+  ///
+  /// The size limit counts the existing contents of the reopened file, the record
+  /// count and age start from zero.
+  ///
+  /// # Errors
+  ///
+  /// Errors from [`IncrementalNamer::open_latest_append`](
+  /// ../incremental/struct.IncrementalNamer.html#method.open_latest_append) and I/O
+  /// errors from reading the file metadata
+
+  pub fn open_latest (
+    file_path : &path::Path, namer : IncrementalNamer, policy : RotationPolicy
+  ) -> Result <Self, FileError> {
+    let (index, path, file) = namer.open_latest_append_index (file_path)?;
+    let bytes = file.metadata()?.len();
+    Ok (RotatingFileWriter {
+      file_path: file_path.to_path_buf(),
+      namer,
+      policy,
+      file,
+      path,
+      index,
+      bytes,
+      records:            0,
+      opened:             time::Instant::now(),
+      retention:          None,
+      on_rotate:          None,
+      on_retention_error: None,
+      sync:               SyncState::new (SyncPolicy::Never)
+    })
+  }
+
+  /// Sets a callback that is invoked after each rotation with the previous and the new
+  /// file path
+  pub fn on_rotate <F> (&mut self, on_rotate : F) -> &mut Self where
+    F : FnMut (&path::Path, &path::Path) + Send + 'static
+  {
+    self.on_rotate = Some (Box::new (on_rotate));
+    self
+  }
+
+  /// Sets a callback that is invoked when applying the retention policy after a
+  /// rotation fails; without one the error is logged as a warning
+  pub fn on_retention_error <F> (&mut self, on_retention_error : F) -> &mut Self where
+    F : FnMut (&FileError) + Send + 'static
+  {
+    self.on_retention_error = Some (Box::new (on_retention_error));
+    self
+  }
+
+  /// Sets a retention policy that is applied to the series after each rotation; the
+  /// current file and the one just rotated away from are never removed but count
+  /// towards the limits. Failing to apply it does not fail the rotation (&#x261e; see
+  /// [`on_retention_error`](#method.on_retention_error)).
+  pub const fn retention (&mut self, retention : Option <RetentionPolicy>)
+    -> &mut Self
+  {
+    self.retention = retention;
+    self
+  }
+
+  /// Sets when written data is synced to disk
+  pub const fn sync_policy (&mut self, policy : SyncPolicy) -> &mut Self {
+    self.sync.set_policy (policy);
+    self
+  }
+
+  /// Syncs all data written to the current file to disk regardless of the sync policy.
+  ///
+  /// # Errors
+  ///
+  /// I/O errors from syncing
+
+  pub fn sync_now (&mut self) -> io::Result <()> {
+    self.sync.sync (&self.file)
+  }
+
+  /// Index of the file currently being written
+  pub const fn index (&self) -> usize {
+    self.index
+  }
+
+  /// Path of the file currently being written
+  pub fn path (&self) -> &path::Path {
+    &self.path
+  }
+
+  /// File currently being written
+  pub const fn file (&self) -> &fs::File {
+    &self.file
+  }
+
+  pub const fn policy (&self) -> &RotationPolicy {
+    &self.policy
+  }
+
+  /// Number of bytes written to the current file
+  pub const fn bytes (&self) -> u64 {
+    self.bytes
+  }
+
+  /// Number of records written to the current file
+  pub const fn records (&self) -> u64 {
+    self.records
+  }
+
+  /// Flushes the current file, opens the next file in the series and applies the
+  /// retention policy, if any.
+  ///
+  /// The next file always has a higher index than the current one, so indices freed by
+  /// the retention policy are not reused. The `on_rotate` callback is invoked as soon
+  /// as the writer has switched to the next file; errors from applying the retention
+  /// policy afterwards are reported to the
+  /// [`on_retention_error`](#method.on_retention_error) callback instead of being
+  /// returned.
+  ///
+  /// # Errors
+  ///
+  /// I/O errors from flushing and errors from
+  /// [`IncrementalNamer::file_new_append`](
+  /// ../incremental/struct.IncrementalNamer.html#method.file_new_append)
+
+  pub fn rotate (&mut self) -> Result <(), FileError> {
+    use io::Write;
+    self.file.flush()?;
+    self.sync.finish (&self.file)?;
+    let start = self.index.checked_add (1).ok_or (FileError::IndexExhausted)?;
+    let (index, path, file) = self.namer.file_new_append_from (&self.file_path, start)?;
+    let previous = std::mem::replace (&mut self.path, path);
+    self.index   = index;
+    self.file    = file;
+    self.bytes   = 0;
+    self.records = 0;
+    self.opened  = time::Instant::now();
+    self.sync    = SyncState::new (self.sync.policy());
+    if let Some (on_rotate) = self.on_rotate.as_mut() {
+      on_rotate (&previous, &self.path);
+    }
+    // never remove the new file or the one just closed
+    if let Some (retention) = self.retention &&
+      let Err (e) = retention.apply_protected (&self.namer, &self.file_path, start - 1)
+    {
+      match self.on_retention_error.as_mut() {
+        Some (on_retention_error) => on_retention_error (&e),
+        None => log::warn!("applying retention after rotating to {} failed: {e}",
+          self.path.display())
+      }
+    }
+    Ok (())
+  }
+
+  /// Returns true if writing a record of `len` bytes should go to a new file
+  fn rotation_due (&self, len : usize) -> bool {
+    if self.records == 0 && self.bytes == 0 {
+      return false
+    }
+    let RotationPolicy { max_bytes, max_records, max_age } = self.policy;
+    max_bytes.is_some_and (|max| self.bytes.saturating_add (len as u64) > max) ||
+    max_records.is_some_and (|max| self.records >= max) ||
+    max_age.is_some_and (|max| self.opened.elapsed() >= max)
+  }
+}
+
+impl io::Write for RotatingFileWriter {
+  fn write (&mut self, buf : &[u8]) -> io::Result <usize> {
+    if self.rotation_due (buf.len()) {
+      self.rotate()?;
+    }
+    self.file.write_all (buf)?;
+    self.bytes   += buf.len() as u64;
+    self.records += 1;
+    self.sync.written (&self.file, buf.len())?;
+    Ok (buf.len())
+  }
+
+  fn write_fmt (&mut self, args : fmt::Arguments) -> io::Result <()> {
+    write_fmt_record (self, args)
+  }
+
+  fn flush (&mut self) -> io::Result <()> {
+    self.file.flush()
+  }
+}
+
+impl Drop for RotatingFileWriter {
+  fn drop (&mut self) {
+    let _ = self.sync.finish (&self.file);
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use std::{io::Write, sync};
+  use crate::file::CounterPosition;
+  use super::*;
+
+  #[test]
+  fn rotate_on_records_and_bytes() {
+    let temp_dir  = tempfile::Builder::new().prefix ("tmp").tempdir().unwrap();
+    let file_path = temp_dir.path().join ("log.txt");
+    let namer     = IncrementalNamer::default()
+      .position (CounterPosition::BeforeLastExtension).build();
+    let policy    = RotationPolicy::default().max_bytes (Some (10))
+      .max_records (Some (2)).build();
+    let mut writer = RotatingFileWriter::new (&file_path, namer, policy).unwrap();
+    writer.sync_policy (SyncPolicy::Bytes (4));
+    for line in ["a\n", "b\n", "c\n", "0123456789abc\n", "d\n"] {
+      writer.write_all (line.as_bytes()).unwrap();
+    }
+    writer.sync_now().unwrap();
+    let read = |name : &str| fs::read_to_string (temp_dir.path().join (name)).unwrap();
+    assert_eq!(read ("log-0.txt"), "a\nb\n");
+    assert_eq!(read ("log-1.txt"), "c\n");
+    assert_eq!(read ("log-2.txt"), "0123456789abc\n");
+    assert_eq!(read ("log-3.txt"), "d\n");
+    assert_eq!(writer.path(), temp_dir.path().join ("log-3.txt"));
+  }
+
+  #[test]
+  fn formatted_lines_are_records() {
+    let temp_dir  = tempfile::Builder::new().prefix ("tmp").tempdir().unwrap();
+    let file_path = temp_dir.path().join ("log");
+    let policy    = RotationPolicy::default().max_records (Some (2)).build();
+    let mut writer = RotatingFileWriter::new (
+      &file_path, IncrementalNamer::default(), policy
+    ).unwrap();
+    for i in 0..3 {
+      writeln!(writer, "line {i}").unwrap();
+    }
+    let read = |name : &str| fs::read_to_string (temp_dir.path().join (name)).unwrap();
+    assert_eq!(read ("log-0"), "line 0\nline 1\n");
+    assert_eq!(read ("log-1"), "line 2\n");
+  }
+
+  #[test]
+  fn rotate_on_age_with_hook() {
+    let temp_dir  = tempfile::Builder::new().prefix ("tmp").tempdir().unwrap();
+    let file_path = temp_dir.path().join ("log");
+    let policy    = RotationPolicy::default().max_age (Some (time::Duration::ZERO))
+      .build();
+    let rotations = sync::Arc::new (sync::Mutex::new (Vec::new()));
+    let mut writer = RotatingFileWriter::new (
+      &file_path, IncrementalNamer::default(), policy
+    ).unwrap();
+    writer.on_rotate ({
+      let rotations = rotations.clone();
+      move |previous, next| rotations.lock().unwrap()
+        .push ((previous.to_path_buf(), next.to_path_buf()))
+    });
+    writer.write_all (b"a").unwrap();
+    writer.write_all (b"b").unwrap();
+    assert_eq!(rotations.lock().unwrap().as_slice(),
+      &[(temp_dir.path().join ("log-0"), temp_dir.path().join ("log-1"))]);
+  }
+
+  #[test]
+  fn resume_latest() {
+    let temp_dir  = tempfile::Builder::new().prefix ("tmp").tempdir().unwrap();
+    let file_path = temp_dir.path().join ("log");
+    let policy    = RotationPolicy::default().max_bytes (Some (4)).build();
+    let mut writer = RotatingFileWriter::open_latest (
+      &file_path, IncrementalNamer::default(), policy
+    ).unwrap();
+    writer.write_all (b"abc").unwrap();
+    writer.write_all (b"def").unwrap();
+    assert_eq!(writer.index(), 1);
+    drop (writer);
+    let mut writer = RotatingFileWriter::open_latest (
+      &file_path, IncrementalNamer::default(), policy
+    ).unwrap();
+    assert_eq!((writer.index(), writer.bytes()), (1, 3));
+    writer.write_all (b"g").unwrap();
+    writer.write_all (b"h").unwrap();
+    assert_eq!(fs::read_to_string (temp_dir.path().join ("log-1")).unwrap(), "defg");
+    assert_eq!(fs::read_to_string (writer.path()).unwrap(), "h");
+  }
+
+  #[test]
+  fn retention_after_rotation() {
+    let temp_dir  = tempfile::Builder::new().prefix ("tmp").tempdir().unwrap();
+    let file_path = temp_dir.path().join ("log");
+    let policy    = RotationPolicy::default().max_records (Some (1)).build();
+    let mut writer = RotatingFileWriter::new (
+      &file_path, IncrementalNamer::default(), policy
+    ).unwrap();
+    writer.retention (Some (RetentionPolicy::default().keep_newest (Some (2)).build()));
+    for line in ["a\n", "b\n", "c\n", "d\n"] {
+      writer.write_all (line.as_bytes()).unwrap();
+    }
+    let mut names = fs::read_dir (temp_dir.path()).unwrap()
+      .map (|entry| entry.unwrap().file_name().into_string().unwrap())
+      .collect::<Vec <_>>();
+    names.sort();
+    assert_eq!(names, ["log-2", "log-3"]);
+  }
+
+  #[test]
+  fn retention_protects_current_files() {
+    let temp_dir  = tempfile::Builder::new().prefix ("tmp").tempdir().unwrap();
+    let file_path = temp_dir.path().join ("log");
+    let policy    = RotationPolicy::default().max_records (Some (1)).build();
+    for retention in [
+      RetentionPolicy::default().keep_newest (Some (0)).build(),
+      RetentionPolicy::default().max_total_bytes (Some (1)).build()
+    ] {
+      let mut writer = RotatingFileWriter::new (
+        &file_path, IncrementalNamer::default(), policy
+      ).unwrap();
+      writer.retention (Some (retention));
+      for line in ["a\n", "b\n", "c\n"] {
+        writer.write_all (line.as_bytes()).unwrap();
+      }
+      let current = writer.path().to_path_buf();
+      let closed  = file_path.with_file_name (format!("log-{}", writer.index() - 1));
+      assert_eq!(fs::read_to_string (&current).unwrap(), "c\n");
+      assert_eq!(fs::read_to_string (&closed).unwrap(), "b\n");
+      assert_eq!(fs::read_dir (temp_dir.path()).unwrap().count(), 2);
+      drop (writer);
+      fs::remove_file (current).unwrap();
+      fs::remove_file (closed).unwrap();
+    }
+  }
+
+  #[cfg(unix)]
+  #[test]
+  fn retention_error_reported_separately() {
+    use std::os::unix::fs::PermissionsExt;
+    use std::sync::{Arc, Mutex};
+    let temp_dir  = tempfile::Builder::new().prefix ("tmp").tempdir().unwrap();
+    let dir       = temp_dir.path().join ("logs");
+    let file_path = dir.join ("log");
+    let policy    = RotationPolicy::default().max_records (Some (1)).build();
+    let mut writer = RotatingFileWriter::new (
+      &file_path, IncrementalNamer::default(), policy
+    ).unwrap();
+    let events = Arc::new (Mutex::new (Vec::new()));
+    writer.retention (Some (RetentionPolicy::default().keep_newest (Some (1)).build()))
+      .on_rotate ({
+        let events = Arc::clone (&events);
+        move |_, new| events.lock().unwrap().push (format!("rotate {}", new.display()))
+      })
+      .on_retention_error ({
+        let events = Arc::clone (&events);
+        move |_| events.lock().unwrap().push ("retention".to_string())
+      });
+    for line in ["a\n", "b\n"] {
+      writer.write_all (line.as_bytes()).unwrap();
+    }
+    // a directory that can not be listed makes applying the retention policy fail,
+    // unless running as root
+    fs::set_permissions (&dir, fs::Permissions::from_mode (0o300)).unwrap();
+    if fs::read_dir (&dir).is_ok() {
+      fs::set_permissions (&dir, fs::Permissions::from_mode (0o755)).unwrap();
+      return
+    }
+    let result = writer.rotate();
+    fs::set_permissions (&dir, fs::Permissions::from_mode (0o755)).unwrap();
+    result.unwrap();
+    writer.write_all (b"c\n").unwrap();
+    assert_eq!(*events.lock().unwrap(), [
+      format!("rotate {}", dir.join ("log-1").display()),
+      format!("rotate {}", dir.join ("log-2").display()),
+      "retention".to_string()
+    ]);
+  }
+}
@@ -0,0 +1,259 @@
+//! Per-run output directories with a metadata manifest
+
+use std::{env, fs, path, process, thread};
+
+use super::{
+  file_new_append_within, safe_join, write_atomic, FileError, IncrementalNamer
+};
+
+/// File name of the manifest written into each run directory
+pub const RUN_MANIFEST : &str = "run.json";
+
+/// What [`RunDir::create`](struct.RunDir.html#method.create) records in the manifest
+/// and how the directory is named
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct RunDirOptions {
+  /// Version of the program, e.g. `env!("CARGO_PKG_VERSION")` in the binary crate
+  pub version  : Option <String>,
+  /// Environment variables whose values are recorded; unset variables are `null` and
+  /// values that are not valid Unicode are converted lossily
+  pub env_vars : Vec <String>,
+  /// Naming scheme of the run directories
+  pub namer    : IncrementalNamer
+}
+
+/// An incrementally named output directory for one run of a program, e.g. `out/run-3/`,
+/// with a `run.json` manifest describing the run.
+///
+/// The manifest records the start time (RFC 3339, UTC), the command line arguments,
+/// the working directory, the process id, the host name, the program version and the
+/// selected environment variables. [`finish`](#method.finish) adds the end time and
+/// exit status; if the `RunDir` is dropped without finishing, the end time is recorded
+/// with exit status `101` when the thread is panicking and `null` otherwise. The
+/// manifest is replaced atomically on each update.
+///
+/// # Examples
+///
+/// ```
+/// # use std::io::Write;
+/// # use rs_utils::file::{RunDir, RunDirOptions};
+/// let temp_dir = tempfile::Builder::new().prefix ("tmp").tempdir().unwrap();
+/// let mut options = RunDirOptions::default();
+/// options.version ("1.2.3").env_vars (&["HOME"]);
+/// let run = RunDir::create (&temp_dir.path().join ("out/run"), &options).unwrap();
+/// assert_eq!(run.path(), temp_dir.path().join ("out/run-0"));
+/// let (_, mut log) = run.file_new_append ("log.txt".as_ref()).unwrap();
+/// writeln!(log, "started").unwrap();
+/// let manifest_path = run.finish (0).unwrap();
+/// let manifest : serde_json::Value =
+///   serde_json::from_slice (&std::fs::read (manifest_path).unwrap()).unwrap();
+/// assert_eq!(manifest["version"], "1.2.3");
+/// assert_eq!(manifest["exit_status"], 0);
+/// assert!(manifest["end_time"].is_string());
+/// ```
+
+#[derive(Debug)]
+pub struct RunDir {
+  path     : path::PathBuf,
+  manifest : serde_json::Map <String, serde_json::Value>,
+  finished : bool
+}
+
+impl RunDirOptions {
+  pub fn version (&mut self, version : &str) -> &mut Self {
+    self.version = Some (version.to_string());
+    self
+  }
+
+  pub fn env_vars (&mut self, env_vars : &[&str]) -> &mut Self {
+    self.env_vars = env_vars.iter().map (ToString::to_string).collect();
+    self
+  }
+
+  pub fn namer (&mut self, namer : IncrementalNamer) -> &mut Self {
+    self.namer = namer;
+    self
+  }
+
+  pub fn build (&mut self) -> Self {
+    self.clone()
+  }
+}
+
+impl RunDir {
+  /// Creates the next run directory named by `options.namer` for `dir_path`,
+  /// recursively creating parent directories, and writes the manifest.
+  ///
+  /// # Errors
+  ///
+  /// - Errors from [`IncrementalNamer::dir_new`](
+  ///   ../incremental/struct.IncrementalNamer.html#method.dir_new)
+  /// - I/O errors from writing the manifest
+
+  pub fn create (dir_path : &path::Path, options : &RunDirOptions)
+    -> Result <Self, FileError>
+  {
+    use serde_json::Value;
+    let path = options.namer.dir_new (dir_path)?;
+    let env_vars = options.env_vars.iter()
+      .map (|name| (name.clone(), env::var_os (name)
+        .map_or (Value::Null, |value| value.to_string_lossy().into())))
+      .collect::<serde_json::Map <_, _>>();
+    let argv = env::args_os().map (|arg| arg.to_string_lossy().into_owned())
+      .collect::<Vec <_>>();
+    let cwd  = env::current_dir().ok().map (|cwd| cwd.to_string_lossy().into_owned());
+    let mut manifest = serde_json::Map::new();
+    manifest.insert ("start_time".to_string(),  now().into());
+    manifest.insert ("end_time".to_string(),    Value::Null);
+    manifest.insert ("exit_status".to_string(), Value::Null);
+    manifest.insert ("argv".to_string(),        argv.into());
+    manifest.insert ("cwd".to_string(),         cwd.into());
+    manifest.insert ("pid".to_string(),         process::id().into());
+    manifest.insert ("hostname".to_string(),    hostname().into());
+    manifest.insert ("version".to_string(),     options.version.clone().into());
+    manifest.insert ("env".to_string(),         env_vars.into());
+    let run_dir = RunDir { path, manifest, finished: false };
+    run_dir.write_manifest()?;
+    Ok (run_dir)
+  }
+
+  /// Path of the run directory
+  pub fn path (&self) -> &path::Path {
+    &self.path
+  }
+
+  pub const fn manifest (&self) -> &serde_json::Map <String, serde_json::Value> {
+    &self.manifest
+  }
+
+  /// Adds or replaces a field of the manifest and rewrites it.
+  ///
+  /// # Errors
+  ///
+  /// I/O errors from writing the manifest
+
+  pub fn set (&mut self, key : &str, value : serde_json::Value)
+    -> Result <(), FileError>
+  {
+    self.manifest.insert (key.to_string(), value);
+    self.write_manifest()
+  }
+
+  /// Joins a relative path onto the run directory, rejecting paths that lead outside
+  /// of it.
+  ///
+  /// # Errors
+  ///
+  /// &#x261e; See [`safe_join`](../lexical/fn.safe_join.html)
+
+  pub fn join (&self, relative : &path::Path) -> Result <path::PathBuf, FileError> {
+    safe_join (&self.path, relative)
+  }
+
+  /// Creates a new file in append mode inside the run directory.
+  ///
+  /// # Errors
+  ///
+  /// &#x261e; See [`file_new_append_within`](../lexical/fn.file_new_append_within.html)
+
+  pub fn file_new_append (&self, relative : &path::Path)
+    -> Result <(path::PathBuf, fs::File), FileError>
+  {
+    file_new_append_within (&self.path, relative)
+  }
+
+  /// Creates the next incrementally named file in append mode inside the run
+  /// directory.
+  ///
+  /// # Errors
+  ///
+  /// - &#x261e; See [`join`](#method.join)
+  /// - Errors from [`IncrementalNamer::file_new_append`](
+  ///   ../incremental/struct.IncrementalNamer.html#method.file_new_append)
+
+  pub fn file_new_append_incremental (&self, relative : &path::Path)
+    -> Result <(path::PathBuf, fs::File), FileError>
+  {
+    IncrementalNamer::default().file_new_append (&self.join (relative)?)
+  }
+
+  /// Records the end time and exit status in the manifest and returns the manifest
+  /// path.
+  ///
+  /// # Errors
+  ///
+  /// I/O errors from writing the manifest
+
+  pub fn finish (mut self, exit_status : i32) -> Result <path::PathBuf, FileError> {
+    self.finished = true;
+    self.record_end (Some (exit_status))?;
+    Ok (self.path.join (RUN_MANIFEST))
+  }
+
+  fn record_end (&mut self, exit_status : Option <i32>) -> Result <(), FileError> {
+    self.manifest.insert ("end_time".to_string(), now().into());
+    self.manifest.insert ("exit_status".to_string(), exit_status.into());
+    self.write_manifest()
+  }
+
+  fn write_manifest (&self) -> Result <(), FileError> {
+    let mut contents = serde_json::to_vec_pretty (&self.manifest)
+      .map_err (|e| FileError::Serialize (e.into()))?;
+    contents.push (b'\n');
+    write_atomic (&self.path.join (RUN_MANIFEST), &contents)
+  }
+}
+
+impl Drop for RunDir {
+  fn drop (&mut self) {
+    if !self.finished {
+      let _ = self.record_end (thread::panicking().then_some (101));
+    }
+  }
+}
+
+fn now() -> String {
+  chrono::Utc::now().to_rfc3339_opts (chrono::SecondsFormat::Millis, true)
+}
+
+fn hostname() -> Option <String> {
+  #[cfg(target_os = "linux")]
+  if let Ok (hostname) = fs::read_to_string ("/proc/sys/kernel/hostname") {
+    return Some (hostname.trim().to_string())
+  }
+  env::var ("HOSTNAME").or_else (|_| env::var ("COMPUTERNAME")).ok()
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn read_manifest (run_path : &path::Path) -> serde_json::Value {
+    serde_json::from_slice (&fs::read (run_path.join (RUN_MANIFEST)).unwrap()).unwrap()
+  }
+
+  #[test]
+  fn manifest_on_drop() {
+    let temp_dir = tempfile::Builder::new().prefix ("tmp").tempdir().unwrap();
+    let options  = RunDirOptions::default().env_vars (&["RS_UTILS_UNSET_VAR"]).build();
+    let mut run  = RunDir::create (&temp_dir.path().join ("run"), &options).unwrap();
+    let run_path = run.path().to_path_buf();
+    let manifest = read_manifest (&run_path);
+    assert_eq!(manifest["pid"], process::id());
+    assert!(manifest["end_time"].is_null());
+    assert!(manifest["env"]["RS_UTILS_UNSET_VAR"].is_null());
+    assert!(manifest["argv"].as_array().is_some_and (|argv| !argv.is_empty()));
+    run.set ("seed", 42.into()).unwrap();
+    assert!(matches!(run.file_new_append ("../escape".as_ref()),
+      Err (FileError::PathEscapesBase)));
+    assert_eq!(run.file_new_append_incremental ("frame.bin".as_ref()).unwrap().0,
+      run_path.join ("frame.bin-0"));
+    drop (run);
+    let manifest = read_manifest (&run_path);
+    assert_eq!(manifest["seed"], 42);
+    assert!(manifest["end_time"].is_string());
+    assert!(manifest["exit_status"].is_null());
+    let run = RunDir::create (&temp_dir.path().join ("run"), &options).unwrap();
+    assert_eq!(run.path(), temp_dir.path().join ("run-1"));
+  }
+}
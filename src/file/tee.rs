@@ -0,0 +1,318 @@
+//! Writing the same stream to several sinks
+
+use std::{fmt, io, thread, time};
+
+use super::write_fmt_record;
+
+/// Callback invoked with the index of a sink and the error that made it fail
+pub type TeeErrorHook = Box <dyn FnMut (usize, &io::Error) + Send>;
+
+/// What a [`TeeWriter`](struct.TeeWriter.html) does when a sink fails
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub enum TeeErrorPolicy {
+  /// Return the error immediately; sinks after the failed one are not written
+  #[default]
+  FailFast,
+  /// Stop writing to the failed sink, report it and continue with the others; writes
+  /// fail once every sink has failed, until the sinks are reset with
+  /// [`reset_failed`](struct.TeeWriter.html#method.reset_failed)
+  Skip,
+  /// Retry the failed sink this many more times, sleeping for the given duration in
+  /// between, then return the error as with `FailFast`
+  Retry {
+    attempts : u32,
+    backoff  : time::Duration
+  }
+}
+
+/// An `io::Write` sink that writes everything to each of its underlying writers in the
+/// order they were added.
+///
+/// Each call to `write` is treated as one record and is written in full to every sink,
+/// so sinks that treat each write as a record, such as
+/// [`RotatingFileWriter`](../rotate/struct.RotatingFileWriter.html),
+/// [`LockedAppendFile`](../lock/struct.LockedAppendFile.html) or
+/// [`DurableFile`](../durable/struct.DurableFile.html), receive the same records. A
+/// retried record continues after the bytes that the sink already accepted, and a
+/// `writeln!` reaches every sink as a single record.
+///
+/// Sinks must be `Send`, so the writer can be used as an `env_logger`
+/// `Target::Pipe`.
+///
+/// # Examples
+///
+/// ```
+/// # use std::io::Write;
+/// # use rs_utils::file::{file_new_append, file_new_append_incremental, TeeWriter};
+/// let temp_dir = tempfile::Builder::new().prefix ("tmp").tempdir().unwrap();
+/// let (log_path, log_file) =
+///   file_new_append_incremental (&temp_dir.path().join ("log")).unwrap();
+/// let copy_path = temp_dir.path().join ("copy");
+/// let mut tee = TeeWriter::new();
+/// tee.push (log_file).push (file_new_append (&copy_path).unwrap())
+///   .push (std::io::sink());
+/// writeln!(tee, "hello").unwrap();
+/// tee.flush().unwrap();
+/// assert_eq!(std::fs::read_to_string (log_path).unwrap(), "hello\n");
+/// assert_eq!(std::fs::read_to_string (copy_path).unwrap(), "hello\n");
+/// ```
+
+#[derive(Default)]
+pub struct TeeWriter {
+  sinks    : Vec <Box <dyn io::Write + Send>>,
+  errors   : Vec <Option <io::Error>>,
+  policy   : TeeErrorPolicy,
+  on_error : Option <TeeErrorHook>
+}
+
+impl TeeWriter {
+  pub fn new() -> Self {
+    Self::default()
+  }
+
+  /// Adds a sink after the existing ones
+  pub fn push <W> (&mut self, sink : W) -> &mut Self where
+    W : io::Write + Send + 'static
+  {
+    self.sinks.push (Box::new (sink));
+    self.errors.push (None);
+    self
+  }
+
+  pub const fn error_policy (&mut self, policy : TeeErrorPolicy) -> &mut Self {
+    self.policy = policy;
+    self
+  }
+
+  /// Sets a callback that is invoked each time a sink fails, after any retries
+  pub fn on_error <F> (&mut self, on_error : F) -> &mut Self where
+    F : FnMut (usize, &io::Error) + Send + 'static
+  {
+    self.on_error = Some (Box::new (on_error));
+    self
+  }
+
+  /// Indices of the sinks skipped after failing under `TeeErrorPolicy::Skip`, with the
+  /// errors that made them fail
+  pub fn failed (&self) -> impl Iterator <Item = (usize, &io::Error)> {
+    self.errors.iter().enumerate()
+      .filter_map (|(index, error)| error.as_ref().map (|error| (index, error)))
+  }
+
+  /// Resumes writing to every skipped sink
+  pub fn reset_failed (&mut self) {
+    self.errors.iter_mut().for_each (|error| *error = None);
+  }
+
+  /// Flushes every sink that has not been skipped, regardless of the error policy, and
+  /// returns the first error.
+  ///
+  /// # Errors
+  ///
+  /// The first I/O error from flushing a sink
+
+  pub fn flush_all (&mut self) -> io::Result <()> {
+    let mut result = Ok (());
+    for (sink, error) in self.sinks.iter_mut().zip (&self.errors) {
+      if error.is_none() && let Err (e) = sink.flush() && result.is_ok() {
+        result = Err (e);
+      }
+    }
+    result
+  }
+
+  /// Applies `f` to every sink that has not been skipped according to the error policy
+  fn for_each_sink <F> (&mut self, mut f : F) -> io::Result <()> where
+    F : FnMut (usize, &mut dyn io::Write) -> io::Result <()>
+  {
+    let (attempts, backoff) = match self.policy {
+      TeeErrorPolicy::Retry { attempts, backoff } => (attempts, backoff),
+      TeeErrorPolicy::FailFast | TeeErrorPolicy::Skip => (0, time::Duration::ZERO)
+    };
+    let mut last_kind = None;
+    for (index, sink) in self.sinks.iter_mut().enumerate() {
+      if self.errors[index].is_some() {
+        continue
+      }
+      let mut result = f (index, sink.as_mut());
+      for _ in 0..attempts {
+        if result.is_ok() {
+          break
+        }
+        thread::sleep (backoff);
+        result = f (index, sink.as_mut());
+      }
+      let Err (e) = result else {
+        continue
+      };
+      if let Some (on_error) = self.on_error.as_mut() {
+        on_error (index, &e);
+      }
+      if self.policy != TeeErrorPolicy::Skip {
+        return Err (e)
+      }
+      last_kind = Some (e.kind());
+      self.errors[index] = Some (e);
+    }
+    if !self.sinks.is_empty() && self.errors.iter().all (Option::is_some) {
+      let kind = last_kind.unwrap_or (io::ErrorKind::BrokenPipe);
+      return Err (io::Error::new (kind, "every tee sink has failed"))
+    }
+    Ok (())
+  }
+}
+
+impl io::Write for TeeWriter {
+  /// Writes the whole buffer to each sink
+  fn write (&mut self, buf : &[u8]) -> io::Result <usize> {
+    // bytes of `buf` accepted by the sink at `current`, kept across retries
+    let (mut current, mut written) = (usize::MAX, 0);
+    self.for_each_sink (|index, sink| {
+      if index != current {
+        (current, written) = (index, 0);
+      }
+      while written < buf.len() {
+        match sink.write (&buf[written..]) {
+          Ok (0) => return Err (io::ErrorKind::WriteZero.into()),
+          Ok (n) => written += n,
+          Err (e) if e.kind() == io::ErrorKind::Interrupted => {}
+          Err (e) => return Err (e)
+        }
+      }
+      Ok (())
+    })?;
+    Ok (buf.len())
+  }
+
+  fn write_fmt (&mut self, args : fmt::Arguments) -> io::Result <()> {
+    write_fmt_record (self, args)
+  }
+
+  /// Flushes each sink according to the error policy; see also
+  /// [`flush_all`](#method.flush_all)
+  fn flush (&mut self) -> io::Result <()> {
+    self.for_each_sink (|_, sink| sink.flush())
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use std::io::Write;
+  use std::sync::{Arc, Mutex};
+  use std::sync::atomic::{AtomicU32, Ordering};
+  use super::*;
+
+  /// A sink that accepts at most two bytes per write and fails the first `failures`
+  /// writes
+  #[derive(Clone, Default)]
+  struct Flaky {
+    buf      : Arc <Mutex <Vec <u8>>>,
+    failures : Arc <AtomicU32>
+  }
+
+  impl Flaky {
+    fn failing (failures : u32) -> Self {
+      Flaky { failures: Arc::new (AtomicU32::new (failures)), .. Self::default() }
+    }
+
+    fn contents (&self) -> Vec <u8> {
+      self.buf.lock().unwrap().clone()
+    }
+  }
+
+  impl Write for Flaky {
+    fn write (&mut self, buf : &[u8]) -> io::Result <usize> {
+      if self.failures.fetch_update (Ordering::SeqCst, Ordering::SeqCst,
+        |failures| failures.checked_sub (1)).is_ok()
+      {
+        return Err (io::Error::other ("flaky"))
+      }
+      let n = buf.len().min (2);
+      self.buf.lock().unwrap().extend_from_slice (&buf[..n]);
+      Ok (n)
+    }
+
+    fn flush (&mut self) -> io::Result <()> {
+      Ok (())
+    }
+  }
+
+  /// A sink that keeps each write as a separate record
+  struct Records (Arc <Mutex <Vec <Vec <u8>>>>);
+
+  impl Write for Records {
+    fn write (&mut self, buf : &[u8]) -> io::Result <usize> {
+      self.0.lock().unwrap().push (buf.to_vec());
+      Ok (buf.len())
+    }
+
+    fn flush (&mut self) -> io::Result <()> {
+      Ok (())
+    }
+  }
+
+  #[test]
+  fn error_policies() {
+    let (good, bad) = (Flaky::default(), Flaky::failing (3));
+    let mut tee = TeeWriter::new();
+    tee.push (bad.clone()).push (good.clone());
+    assert!(tee.write_all (b"abc").is_err());
+    assert!(good.contents().is_empty());
+
+    let reported = Arc::new (Mutex::new (Vec::new()));
+    let reported_hook = Arc::clone (&reported);
+    tee.error_policy (TeeErrorPolicy::Skip)
+      .on_error (move |index, _| reported_hook.lock().unwrap().push (index));
+    tee.write_all (b"abc").unwrap();
+    tee.write_all (b"def").unwrap();
+    assert_eq!(good.contents(), b"abcdef");
+    assert_eq!(tee.failed().map (|(index, _)| index).collect::<Vec <_>>(), [0]);
+    assert_eq!(*reported.lock().unwrap(), [0]);
+
+    tee.reset_failed();
+    bad.failures.store (2, Ordering::SeqCst);
+    tee.error_policy (TeeErrorPolicy::Retry {
+      attempts: 2, backoff: time::Duration::from_millis (1)
+    });
+    tee.write_all (b"ghi").unwrap();
+    assert_eq!(bad.contents(), b"ghi");
+    assert_eq!(tee.failed().count(), 0);
+    tee.flush_all().unwrap();
+
+    let mut tee = TeeWriter::new();
+    tee.push (Flaky::failing (1)).error_policy (TeeErrorPolicy::Skip);
+    assert!(tee.write_all (b"x").is_err());
+    assert!(tee.write_all (b"y").is_err());
+    assert!(tee.flush().is_err());
+  }
+
+  #[test]
+  fn formatted_records() {
+    let mut tee = TeeWriter::new();
+    let records = Arc::new (Mutex::new (Vec::new()));
+    tee.push (Records (Arc::clone (&records)));
+    writeln!(tee, "a {} b", 1).unwrap();
+    assert_eq!(*records.lock().unwrap(), [b"a 1 b\n".to_vec()]);
+  }
+
+  #[cfg(feature = "env-logger-format")]
+  #[test]
+  fn env_logger_pipe() {
+    use log::Log;
+    let temp_dir  = tempfile::Builder::new().prefix ("tmp").tempdir().unwrap();
+    let file_path = temp_dir.path().join ("log");
+    let mut tee = TeeWriter::new();
+    let copy = Flaky::default();
+    tee.push (crate::file::file_new_append (&file_path).unwrap()).push (copy.clone());
+    let logger = env_logger::Builder::new()
+      .target (env_logger::Target::Pipe (Box::new (tee)))
+      .format (|buf, record| writeln!(buf, "{}", record.args()))
+      .filter_level (log::LevelFilter::Info)
+      .build();
+    logger.log (&log::Record::builder().args (format_args!("hello"))
+      .level (log::Level::Info).build());
+    logger.flush();
+    assert_eq!(std::fs::read_to_string (&file_path).unwrap(), "hello\n");
+    assert_eq!(copy.contents(), b"hello\n");
+  }
+}
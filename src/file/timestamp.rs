@@ -0,0 +1,176 @@
+//! Timestamped file naming
+
+use std::{fmt, fs, io, path};
+
+use super::{file_new_append, CounterPosition, FileError, IncrementalNamer};
+
+/// Default timestamp pattern, e.g. `2026-10-18T07-26-00`, which sorts chronologically
+/// and contains no characters that are reserved in file names on common platforms
+pub const TIMESTAMP_PATTERN : &str = "%Y-%m-%dT%H-%M-%S";
+
+/// Time zone used to format timestamps
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub enum TimeZone {
+  #[default]
+  Utc,
+  Local
+}
+
+/// Returns the file path with the current time formatted by the `strftime`-style
+/// `pattern` inserted before the file extension, if one is present; compound extensions
+/// such as `tar.gz` are kept whole.
+///
+/// When a file with the timestamped name already exists, the incremental suffix of
+/// [`file_path_incremental_with_extension`](
+/// ../fn.file_path_incremental_with_extension.html) is added after the timestamp,
+/// giving e.g. `capture-2026-10-18T07-26-00-0.bin`.
+///
+/// This function only queries for the next available filename, no directories or files
+/// are created.
+///
+/// # Examples
+///
+/// ```
+/// # use std::path::Path;
+/// # use rs_utils::file::{file_path_timestamped, TimeZone};
+/// let file_path = Path::new ("somedir/capture.bin");
+/// let path = file_path_timestamped (file_path, "%Y", TimeZone::Utc).unwrap();
+/// let name = path.file_name().unwrap().to_str().unwrap();
+/// assert!(name.starts_with ("capture-") && name.ends_with (".bin"));
+/// assert_eq!(name.len(), "capture-YYYY.bin".len());
+/// ```
+///
+/// # Errors
+///
+/// - Not a file (&#x261e; see [`is_file`](../fn.is_file.html))
+/// - Invalid timestamp pattern: the pattern is not understood or it formats to an empty
+///   string or one containing a path separator
+/// - Index exhausted
+
+pub fn file_path_timestamped (
+  file_path : &path::Path, pattern : &str, time_zone : TimeZone
+) -> Result <path::PathBuf, FileError> {
+  file_path_timestamped_at (file_path, pattern, time_zone, chrono::Utc::now())
+}
+
+/// Atomically creates a file named as by
+/// [`file_path_timestamped`](fn.file_path_timestamped.html) in append mode, recursively
+/// creating parent directories, and returns the path that was created.
+///
+/// If the timestamped name is taken, including by a concurrent process, the file is
+/// created with the first available incremental suffix instead.
+///
+/// # Errors
+///
+/// &#x261e; See [`file_path_timestamped`](fn.file_path_timestamped.html) and
+/// [`IncrementalNamer::file_new_append`](
+/// ../incremental/struct.IncrementalNamer.html#method.file_new_append)
+
+pub fn file_new_append_timestamped (
+  file_path : &path::Path, pattern : &str, time_zone : TimeZone
+) -> Result <(path::PathBuf, fs::File), FileError> {
+  file_new_append_timestamped_at (file_path, pattern, time_zone, chrono::Utc::now())
+}
+
+fn file_path_timestamped_at (
+  file_path : &path::Path,
+  pattern   : &str,
+  time_zone : TimeZone,
+  now       : chrono::DateTime <chrono::Utc>
+) -> Result <path::PathBuf, FileError> {
+  let timestamp = format_timestamp (pattern, time_zone, now)?;
+  let file_pathbuf = timestamp_namer ("")
+    .path_for_counter (file_path, &timestamp)?;
+  if !file_pathbuf.try_exists()? {
+    return Ok (file_pathbuf)
+  }
+  timestamp_namer (&timestamp).next_path (file_path)
+}
+
+fn file_new_append_timestamped_at (
+  file_path : &path::Path,
+  pattern   : &str,
+  time_zone : TimeZone,
+  now       : chrono::DateTime <chrono::Utc>
+) -> Result <(path::PathBuf, fs::File), FileError> {
+  let timestamp = format_timestamp (pattern, time_zone, now)?;
+  let file_pathbuf = timestamp_namer ("")
+    .path_for_counter (file_path, &timestamp)?;
+  match file_new_append (&file_pathbuf) {
+    Ok (file) => Ok ((file_pathbuf, file)),
+    Err (e) if e.kind() == io::ErrorKind::AlreadyExists =>
+      timestamp_namer (&timestamp).file_new_append (file_path),
+    Err (e) => Err (e)
+  }
+}
+
+/// Namer inserting `-<timestamp>-N` before the extension; with an empty timestamp the
+/// counter position is used for the timestamp itself
+fn timestamp_namer (timestamp : &str) -> IncrementalNamer {
+  let separator = if timestamp.is_empty() {
+    "-".to_string()
+  } else {
+    format!("-{timestamp}-")
+  };
+  IncrementalNamer::default().separator (&separator)
+    .position (CounterPosition::BeforeKnownExtension).build()
+}
+
+fn format_timestamp (
+  pattern : &str, time_zone : TimeZone, now : chrono::DateTime <chrono::Utc>
+) -> Result <String, FileError> {
+  use fmt::Write;
+  let mut timestamp = String::new();
+  let result = match time_zone {
+    TimeZone::Utc   => write!(timestamp, "{}", now.format (pattern)),
+    TimeZone::Local => write!(timestamp, "{}",
+      now.with_timezone (&chrono::Local).format (pattern))
+  };
+  if result.is_err() || timestamp.is_empty() || timestamp.contains (path::is_separator)
+  {
+    return Err (FileError::InvalidTimestampPattern)
+  }
+  Ok (timestamp)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn now() -> chrono::DateTime <chrono::Utc> {
+    use chrono::TimeZone as _;
+    chrono::Utc.with_ymd_and_hms (2026, 10, 18, 7, 26, 0).unwrap()
+  }
+
+  #[test]
+  fn collision_fallback() {
+    let temp_dir  = tempfile::Builder::new().prefix ("tmp").tempdir().unwrap();
+    let file_path = temp_dir.path().join ("capture.bin");
+    let names = std::iter::repeat_with (|| file_new_append_timestamped_at (
+      &file_path, TIMESTAMP_PATTERN, TimeZone::Utc, now()
+    ).unwrap().0).take (3).collect::<Vec <_>>();
+    assert_eq!(names, [
+      temp_dir.path().join ("capture-2026-10-18T07-26-00.bin"),
+      temp_dir.path().join ("capture-2026-10-18T07-26-00-0.bin"),
+      temp_dir.path().join ("capture-2026-10-18T07-26-00-1.bin")
+    ]);
+    assert_eq!(
+      file_path_timestamped_at (&file_path, TIMESTAMP_PATTERN, TimeZone::Utc, now())
+        .unwrap(),
+      temp_dir.path().join ("capture-2026-10-18T07-26-00-2.bin"));
+    assert_eq!(
+      file_path_timestamped_at (&file_path, "%Y%m%d", TimeZone::Utc, now()).unwrap(),
+      temp_dir.path().join ("capture-20261018.bin"));
+  }
+
+  #[test]
+  fn invalid_pattern() {
+    for pattern in ["", "%Y/%m", "%Q"] {
+      let e = file_path_timestamped_at (
+        path::Path::new ("file"), pattern, TimeZone::Local, now()
+      ).unwrap_err();
+      assert!(matches!(e, FileError::InvalidTimestampPattern));
+      assert_eq!(e.kind(), io::ErrorKind::InvalidInput);
+    }
+  }
+}